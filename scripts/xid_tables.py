@@ -0,0 +1,78 @@
+#!/usr/bin/env python3
+"""Generates src/scanner/xid.rs from the Unicode Character Database.
+
+Usage: scripts/xid_tables.py <DerivedCoreProperties.txt> <unicode version>
+
+DerivedCoreProperties.txt is in the UCD, e.g.
+https://www.unicode.org/Public/18.0.0/ucd/DerivedCoreProperties.txt
+"""
+
+import sys
+
+PROPERTIES = ("XID_Start", "XID_Continue")
+
+
+def read_ranges(path):
+    ranges = {name: [] for name in PROPERTIES}
+    with open(path, encoding="utf-8") as lines:
+        for line in lines:
+            fields = line.split("#", 1)[0].split(";")
+            if len(fields) != 2 or fields[1].strip() not in ranges:
+                continue
+            first, _, last = fields[0].strip().partition("..")
+            ranges[fields[1].strip()].append((int(first, 16), int(last or first, 16)))
+    return {name: merge(found) for name, found in ranges.items()}
+
+
+def merge(ranges):
+    merged = []
+    for first, last in sorted(ranges):
+        if merged and first <= merged[-1][1] + 1:
+            merged[-1] = (merged[-1][0], max(merged[-1][1], last))
+        else:
+            merged.append((first, last))
+    return merged
+
+
+def table(name, ranges):
+    rows = "".join(f"    ('\\u{{{first:x}}}', '\\u{{{last:x}}}'),\n" for first, last in ranges)
+    return f"static {name.upper()}: &[(char, char)] = &[\n{rows}];\n"
+
+
+def main():
+    path, version = sys.argv[1], sys.argv[2]
+    ranges = read_ranges(path)
+    print(f"""\
+// Generated by scripts/xid_tables.py from the Unicode {version}
+// DerivedCoreProperties.txt. Do not edit by hand.
+
+/// Whether `c` has Unicode's `XID_Start` property.
+pub fn is_xid_start(c: char) -> bool {{
+    contains(XID_START, c)
+}}
+
+/// Whether `c` has Unicode's `XID_Continue` property.
+pub fn is_xid_continue(c: char) -> bool {{
+    contains(XID_CONTINUE, c)
+}}
+
+/// Binary searches a sorted table of disjoint inclusive ranges.
+fn contains(table: &[(char, char)], c: char) -> bool {{
+    table
+        .binary_search_by(|&(first, last)| {{
+            if last < c {{
+                std::cmp::Ordering::Less
+            }} else if first > c {{
+                std::cmp::Ordering::Greater
+            }} else {{
+                std::cmp::Ordering::Equal
+            }}
+        }})
+        .is_ok()
+}}
+""")
+    print("\n".join(table(name, ranges[name]) for name in PROPERTIES), end="")
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,227 @@
+use std::fmt::{self, Display};
+use std::iter::Peekable;
+use std::vec::IntoIter;
+
+use crate::tokens::{Keyword, Token, TokenType};
+
+/// What a [`SyntaxNode`] groups together.
+#[derive(strum_macros::Display, Debug, Clone, Copy, PartialEq)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum SyntaxKind {
+    Program,
+    VarDecl,
+    FunDecl,
+    ClassDecl,
+    Method,
+    Statement,
+    Block,
+    /// Tokens between matching `(` and `)`, parentheses included.
+    Parenthesized,
+    /// A closing `)` or `}` with nothing to close.
+    Error,
+}
+
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(Token),
+}
+
+/// A concrete syntax tree: unlike [`crate::stmts::Stmt`] it keeps every
+/// token with its trivia and never rejects input, so it can be printed back
+/// to the exact text it was built from. Nodes only record structure
+/// (declarations, statements, blocks and parentheses), which is what a
+/// formatter needs to decide indentation and line breaks.
+pub struct SyntaxNode {
+    pub kind: SyntaxKind,
+    pub children: Vec<SyntaxElement>,
+}
+
+type Tokens = Peekable<IntoIter<Token>>;
+
+fn is_closing(token: &Token) -> bool {
+    matches!(
+        token.token_type,
+        TokenType::RightBrace | TokenType::RightParen
+    )
+}
+
+/// Builds the tree for a whole file from tokens produced by
+/// [`crate::scanner::tokenize::scanner_lossless`].
+pub fn build_cst(tokens: Vec<Token>) -> SyntaxNode {
+    let mut tokens = tokens.into_iter().peekable();
+    let mut children = Vec::new();
+    while let Some(token) = tokens.peek() {
+        let element = match token.token_type {
+            TokenType::EOF => SyntaxElement::Token(tokens.next().unwrap()),
+            _ if is_closing(token) => SyntaxElement::Node(SyntaxNode {
+                kind: SyntaxKind::Error,
+                children: vec![SyntaxElement::Token(tokens.next().unwrap())],
+            }),
+            _ => SyntaxElement::Node(build_item(&mut tokens, false)),
+        };
+        children.push(element);
+    }
+    SyntaxNode {
+        kind: SyntaxKind::Program,
+        children,
+    }
+}
+
+/// One declaration or statement. It runs to a `;` or a closing `}` at its
+/// own nesting level, carrying on through an `else`.
+fn build_item(tokens: &mut Tokens, in_class: bool) -> SyntaxNode {
+    let kind = match tokens.peek().map(|token| &token.token_type) {
+        Some(TokenType::Keyword { kw, .. }) => match kw {
+            Keyword::Var => SyntaxKind::VarDecl,
+            Keyword::Fun => SyntaxKind::FunDecl,
+            Keyword::Class => SyntaxKind::ClassDecl,
+            _ => SyntaxKind::Statement,
+        },
+        Some(TokenType::LeftBrace) => return build_block(tokens, false),
+        _ if in_class => SyntaxKind::Method,
+        _ => SyntaxKind::Statement,
+    };
+    let mut children = Vec::new();
+    while let Some(token) = tokens.peek() {
+        let ended = match token.token_type {
+            TokenType::EOF | TokenType::RightBrace | TokenType::RightParen => break,
+            TokenType::LeftBrace => {
+                let block = build_block(tokens, kind == SyntaxKind::ClassDecl);
+                children.push(SyntaxElement::Node(block));
+                true
+            }
+            TokenType::LeftParen => {
+                children.push(SyntaxElement::Node(build_parenthesized(tokens)));
+                false
+            }
+            TokenType::Semicolon => {
+                children.push(SyntaxElement::Token(tokens.next().unwrap()));
+                true
+            }
+            _ => {
+                children.push(SyntaxElement::Token(tokens.next().unwrap()));
+                false
+            }
+        };
+        let continues = tokens.peek().is_some_and(|token| {
+            matches!(
+                token.token_type,
+                TokenType::Keyword {
+                    kw: Keyword::Else,
+                    ..
+                }
+            )
+        });
+        if ended && !continues {
+            break;
+        }
+    }
+    SyntaxNode { kind, children }
+}
+
+/// A `{ ... }` block whose items are methods when it is a class body.
+fn build_block(tokens: &mut Tokens, is_class_body: bool) -> SyntaxNode {
+    let mut children = vec![SyntaxElement::Token(tokens.next().unwrap())];
+    while let Some(token) = tokens.peek() {
+        match token.token_type {
+            TokenType::EOF => break,
+            TokenType::RightBrace => {
+                children.push(SyntaxElement::Token(tokens.next().unwrap()));
+                break;
+            }
+            TokenType::RightParen => children.push(SyntaxElement::Node(SyntaxNode {
+                kind: SyntaxKind::Error,
+                children: vec![SyntaxElement::Token(tokens.next().unwrap())],
+            })),
+            _ => children.push(SyntaxElement::Node(build_item(tokens, is_class_body))),
+        }
+    }
+    SyntaxNode {
+        kind: SyntaxKind::Block,
+        children,
+    }
+}
+
+fn build_parenthesized(tokens: &mut Tokens) -> SyntaxNode {
+    let mut children = vec![SyntaxElement::Token(tokens.next().unwrap())];
+    while let Some(token) = tokens.peek() {
+        match token.token_type {
+            TokenType::EOF | TokenType::RightBrace => break,
+            TokenType::RightParen => {
+                children.push(SyntaxElement::Token(tokens.next().unwrap()));
+                break;
+            }
+            TokenType::LeftParen => children.push(SyntaxElement::Node(build_parenthesized(tokens))),
+            _ => children.push(SyntaxElement::Token(tokens.next().unwrap())),
+        }
+    }
+    SyntaxNode {
+        kind: SyntaxKind::Parenthesized,
+        children,
+    }
+}
+
+/// Writes the source text back out, trivia included.
+impl Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => write!(f, "{node}")?,
+                SyntaxElement::Token(token) => {
+                    for trivia in &token.leading_trivia {
+                        f.write_str(&trivia.text)?;
+                    }
+                    f.write_str(&token.lexeme)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Prints the tree one element per line, indented by depth, with the byte
+/// range of every token and piece of trivia.
+pub fn print_cst(node: &SyntaxNode, depth: usize) {
+    println!("{}{}", "  ".repeat(depth), node.kind);
+    for child in &node.children {
+        match child {
+            SyntaxElement::Node(child) => print_cst(child, depth + 1),
+            SyntaxElement::Token(token) => {
+                let indent = "  ".repeat(depth + 1);
+                for trivia in &token.leading_trivia {
+                    println!(
+                        "{indent}{} {:?} {}..{}",
+                        trivia.kind, trivia.text, trivia.span.start, trivia.span.end
+                    );
+                }
+                println!(
+                    "{indent}{} {:?} {}..{}",
+                    token.token_type, token.lexeme, token.span.start, token.span.end
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::scanner::tokenize::scanner_lossless;
+
+    /// Every fixture, malformed ones included, prints back byte for byte.
+    #[test]
+    fn round_trips_fixtures() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/cst");
+        let mut checked = 0;
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let source = fs::read_to_string(&path).unwrap();
+            let (tokens, _) = scanner_lossless(&source);
+            assert_eq!(build_cst(tokens).to_string(), source, "{}", path.display());
+            checked += 1;
+        }
+        assert!(checked > 0, "no fixtures in {dir}");
+    }
+}
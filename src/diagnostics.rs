@@ -0,0 +1,227 @@
+use std::fmt::Display;
+use std::io::{self, IsTerminal};
+
+use crate::errors::{ParseError, RuntimeError, ScanError};
+use crate::tokens::Span;
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// How errors are written to stderr.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ErrorFormat {
+    /// One `[line N] Error...` line per error, the format the codecrafters
+    /// tests expect.
+    Plain,
+    /// A rustc-style report quoting the source with the error underlined.
+    Rich { color: bool },
+}
+
+impl ErrorFormat {
+    /// Picks the format asked for with `--error-format`, defaulting to a
+    /// colored rich report when stderr is a terminal and plain otherwise.
+    pub fn detect(requested: Option<&str>) -> Result<Self, String> {
+        let is_terminal = io::stderr().is_terminal();
+        match requested {
+            None if is_terminal => Ok(ErrorFormat::Rich { color: true }),
+            None | Some("plain") => Ok(ErrorFormat::Plain),
+            Some("rich") => Ok(ErrorFormat::Rich { color: is_terminal }),
+            Some(other) => Err(format!("Unknown error format: {other}")),
+        }
+    }
+}
+
+/// Everything needed to render an error against its source.
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+    pub help: Option<String>,
+    pub notes: Vec<String>,
+}
+
+pub trait ToDiagnostic {
+    fn to_diagnostic(&self) -> Diagnostic;
+}
+
+impl ToDiagnostic for ScanError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            ScanError::UnexpectedCharacter {
+                character, span, ..
+            } => Diagnostic {
+                code: "E0001",
+                message: format!("Unexpected character: {character}"),
+                span: *span,
+                help: None,
+                notes: Vec::new(),
+            },
+            ScanError::StrayUnicode {
+                character, span, ..
+            } => Diagnostic {
+                code: "E0008",
+                message: format!(
+                    "Unexpected character '{character}' (U+{:04X})",
+                    *character as u32
+                ),
+                span: *span,
+                help: Some(
+                    "non-ASCII text is only allowed in identifiers, strings and comments"
+                        .to_string(),
+                ),
+                notes: Vec::new(),
+            },
+            ScanError::UnterminatedString { span, .. } => Diagnostic {
+                code: "E0002",
+                message: "Unterminated string.".to_string(),
+                span: *span,
+                help: Some("add a closing `\"`".to_string()),
+                notes: Vec::new(),
+            },
+            ScanError::UnterminatedComment { span, .. } => Diagnostic {
+                code: "E0006",
+                message: "Unterminated block comment.".to_string(),
+                span: *span,
+                help: Some("every `/*` needs a matching `*/`, including nested ones".to_string()),
+                notes: Vec::new(),
+            },
+            ScanError::MalformedNumber { literal, span, .. } => Diagnostic {
+                code: "E0007",
+                message: format!("Malformed number literal: {literal}"),
+                span: *span,
+                help: Some(
+                    "numbers look like `42`, `1_000.5e-3`, `0xFF` or `0b1010`; \
+                     `_` may only separate digits"
+                        .to_string(),
+                ),
+                notes: Vec::new(),
+            },
+            ScanError::InvalidEscape { sequence, span, .. } => Diagnostic {
+                code: "E0005",
+                message: format!("Invalid escape sequence: {sequence}"),
+                span: *span,
+                help: Some(
+                    "valid escapes are \\\", \\\\, \\n, \\t, \\r, \\0 and \\u{XXXX}".to_string(),
+                ),
+                notes: Vec::new(),
+            },
+        }
+    }
+}
+
+impl ToDiagnostic for ParseError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic {
+            code: "E0003",
+            message: self.message.clone(),
+            span: self.token.span,
+            help: None,
+            notes: Vec::new(),
+        }
+    }
+}
+
+impl ToDiagnostic for RuntimeError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        let help = self
+            .message
+            .starts_with("Undefined variable")
+            .then(|| "declare it with `var` before using it".to_string());
+        Diagnostic {
+            code: "E0004",
+            message: self.message.clone(),
+            span: self.span,
+            help,
+            notes: self
+                .stack_trace()
+                .map(|trace| trace.lines().map(str::to_string).collect())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Writes errors for one source file in the chosen [`ErrorFormat`].
+pub struct Reporter<'a> {
+    filename: &'a str,
+    source: &'a str,
+    format: ErrorFormat,
+}
+
+impl<'a> Reporter<'a> {
+    pub fn new(filename: &'a str, source: &'a str, format: ErrorFormat) -> Self {
+        Self {
+            filename,
+            source,
+            format,
+        }
+    }
+
+    pub fn report<E: Display + ToDiagnostic>(&self, error: &E) {
+        match self.format {
+            ErrorFormat::Plain => eprintln!("{error}"),
+            ErrorFormat::Rich { color } => {
+                eprint!("{}", self.render(&error.to_diagnostic(), color))
+            }
+        }
+    }
+
+    fn render(&self, diagnostic: &Diagnostic, color: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if color {
+                format!("{style}{text}{RESET}")
+            } else {
+                text.to_string()
+            }
+        };
+
+        // Only the first line of a multi-line span (such as a string) is
+        // quoted and underlined.
+        let start = diagnostic.span.start.min(self.source.len());
+        let line_start = self.source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = self.source[start..]
+            .find('\n')
+            .map_or(self.source.len(), |i| start + i);
+        let text = self.source[line_start..line_end].trim_end_matches('\r');
+        let end = diagnostic.span.end.clamp(start, line_start + text.len());
+
+        // Tabs are kept in the padding so the carets line up with the text.
+        let padding: String = self.source[line_start..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let underline = "^".repeat(self.source[start..end].chars().count().max(1));
+        let column = diagnostic.span.column.max(1);
+
+        let line = self.source[..start].matches('\n').count() + 1;
+        let line_number = line.to_string();
+        let gutter = " ".repeat(line_number.len());
+        let bar = paint(BLUE, "|");
+
+        let mut rendered = format!(
+            "{}{}\n",
+            paint(RED, &format!("error[{}]", diagnostic.code)),
+            paint(BOLD, &format!(": {}", diagnostic.message)),
+        );
+        rendered.push_str(&format!(
+            "{gutter}{} {}:{}:{column}\n",
+            paint(BLUE, "-->"),
+            self.filename,
+            line,
+        ));
+        rendered.push_str(&format!("{gutter} {bar}\n"));
+        rendered.push_str(&format!("{} {bar} {text}\n", paint(BLUE, &line_number)));
+        rendered.push_str(&format!(
+            "{gutter} {bar} {padding}{}\n",
+            paint(RED, &underline)
+        ));
+        if let Some(help) = &diagnostic.help {
+            rendered.push_str(&format!("{gutter} {} help: {help}\n", paint(BLUE, "=")));
+        }
+        for note in &diagnostic.notes {
+            rendered.push_str(&format!("{gutter} {} note: {note}\n", paint(BLUE, "=")));
+        }
+        rendered
+    }
+}
@@ -0,0 +1,158 @@
+use thiserror::Error;
+
+use crate::tokens::{Span, Token, TokenType};
+
+/// A lexical error. Scanning carries on past it, so a single run reports
+/// every bad character in the file.
+#[derive(Debug, Error)]
+pub enum ScanError {
+    #[error("[line {line}] Error: Unexpected character: {character}")]
+    UnexpectedCharacter {
+        character: char,
+        line: usize,
+        span: Span,
+    },
+    /// A non-ASCII character outside a string or comment. The code point is
+    /// shown because it is often invisible or a look-alike of ASCII.
+    #[error(
+        "[line {line}] Error: Unexpected character '{character}' (U+{:04X}) at column {}.",
+        *character as u32,
+        span.column
+    )]
+    StrayUnicode {
+        character: char,
+        line: usize,
+        span: Span,
+    },
+    #[error("[line {line}] Error: Unterminated string.")]
+    UnterminatedString { line: usize, span: Span },
+    #[error("[line {line}] Error: Unterminated block comment.")]
+    UnterminatedComment { line: usize, span: Span },
+    #[error("[line {line}] Error: Malformed number literal: {literal}")]
+    MalformedNumber {
+        literal: String,
+        line: usize,
+        span: Span,
+    },
+    #[error("[line {line}] Error: Invalid escape sequence: {sequence}")]
+    InvalidEscape {
+        sequence: String,
+        line: usize,
+        span: Span,
+    },
+}
+
+impl ScanError {
+    pub fn line(&self) -> usize {
+        match self {
+            ScanError::UnexpectedCharacter { line, .. }
+            | ScanError::StrayUnicode { line, .. }
+            | ScanError::UnterminatedString { line, .. }
+            | ScanError::UnterminatedComment { line, .. }
+            | ScanError::MalformedNumber { line, .. }
+            | ScanError::InvalidEscape { line, .. } => *line,
+        }
+    }
+}
+
+/// A syntax error, or a static error found by the resolver, reported against
+/// the token where things went wrong.
+#[derive(Debug, Error)]
+#[error("[line {}] Error{}: {message}", token.line, location(token))]
+pub struct ParseError {
+    /// Boxed to keep `ParseResult` small.
+    pub token: Box<Token>,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new(token: &Token, message: &str) -> Self {
+        Self {
+            token: Box::new(token.clone()),
+            message: message.to_string(),
+        }
+    }
+}
+
+fn location(token: &Token) -> String {
+    match token.token_type {
+        TokenType::EOF => " at end".to_string(),
+        _ => format!(" at '{}'", token.lexeme),
+    }
+}
+
+/// A function call that was active when a runtime error was raised.
+#[derive(Debug)]
+pub struct StackFrame {
+    pub function: String,
+    /// Line that was executing inside `function`.
+    pub line: usize,
+}
+
+/// An error raised while running the program, tied to the source location
+/// whose evaluation failed. As it propagates out of function calls it collects a
+/// stack trace, printed innermost frame first.
+#[derive(Debug, Error)]
+#[error("{message}\n{}", render_trace(trace, *line))]
+pub struct RuntimeError {
+    pub message: String,
+    /// Source range to point at.
+    pub span: Span,
+    pub trace: Vec<StackFrame>,
+    /// Line executing in the innermost frame not yet recorded in `trace`.
+    line: usize,
+}
+
+impl RuntimeError {
+    pub fn new(token: &Token, message: impl Into<String>) -> Self {
+        Self::at_line(token.line, token.span, message)
+    }
+
+    /// An error without a token to blame, as raised by the bytecode VM,
+    /// which only keeps the line and span each instruction came from.
+    pub fn at_line(line: usize, span: Span, message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            span,
+            trace: Vec::new(),
+            line,
+        }
+    }
+
+    /// Points the error at `span` instead of the token, e.g. a whole binary
+    /// expression rather than its operator.
+    pub fn at(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
+
+    /// The call stack as printed after the message, or `None` when the error
+    /// was raised outside of any function.
+    pub fn stack_trace(&self) -> Option<String> {
+        (!self.trace.is_empty()).then(|| render_trace(&self.trace, self.line))
+    }
+
+    /// Records that the error propagated out of `function`, which had been
+    /// called from `call_line`.
+    pub fn unwind(&mut self, function: &str, call_line: usize) {
+        self.trace.push(StackFrame {
+            function: function.to_string(),
+            line: self.line,
+        });
+        self.line = call_line;
+    }
+}
+
+/// Errors raised outside of any function keep the plain `[line N]` suffix;
+/// otherwise every frame is listed down to the top-level script.
+fn render_trace(trace: &[StackFrame], script_line: usize) -> String {
+    if trace.is_empty() {
+        return format!("[line {script_line}]");
+    }
+    let mut rendered = String::new();
+    for frame in trace {
+        rendered.push_str(&format!("[line {}] in {}()\n", frame.line, frame.function));
+    }
+    rendered.push_str(&format!("[line {script_line}] in script"));
+    rendered
+}
@@ -0,0 +1,42 @@
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::rc::Rc;
+
+use super::environment::Environment;
+use super::evaluate::Value;
+use super::gc::Heap;
+use crate::stmts::FunctionDecl;
+
+/// A user-defined function together with the environment it was declared in,
+/// which is what lets inner functions keep reading their outer locals.
+pub struct LoxFunction {
+    pub declaration: Rc<FunctionDecl>,
+    pub closure: Rc<RefCell<Environment>>,
+    /// Set for a class's `init` method, which always returns `this`.
+    pub is_initializer: bool,
+}
+
+impl LoxFunction {
+    pub fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+
+    /// Returns a copy of this method whose closure defines `this` as
+    /// `instance`.
+    pub fn bind(&self, instance: Value, heap: &mut Heap) -> Rc<LoxFunction> {
+        let mut scope = Environment::with_enclosing(Rc::clone(&self.closure));
+        scope.define("this", instance);
+        let closure = heap.environment(scope);
+        heap.function(LoxFunction {
+            declaration: Rc::clone(&self.declaration),
+            closure,
+            is_initializer: self.is_initializer,
+        })
+    }
+}
+
+impl Display for LoxFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<fn {}>", self.declaration.name.lexeme)
+    }
+}
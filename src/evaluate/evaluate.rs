@@ -1,15 +1,38 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt::Display;
-use std::io::{self, Write};
-use std::process;
+use std::rc::Rc;
 
-use crate::exprs::Expr;
-use crate::tokens::{Operator, TokenType};
+use super::callable::LoxFunction;
+use super::class::{LoxClass, LoxInstance};
+use super::environment::Environment;
+use super::gc::{GcOptions, Heap};
+use super::interner::Interner;
+use crate::errors::RuntimeError;
+use crate::exprs::{Depth, Expr};
+use crate::stmts::Stmt;
+use crate::tokens::{Keyword, Operator, Token, TokenType};
+use crate::utils::{format_number, NumberStyle};
+use crate::values::{natives, object, Kind, LoxValue, Native, FRAMES_MAX};
 
-enum Value {
+/// A Lox value. Everything except `nil`, booleans and numbers lives on the
+/// heap behind an `Rc`, so copying a value (into a variable, an argument or
+/// a field) only bumps a reference count. Strings are interned: equal
+/// strings share one allocation.
+///
+/// Reference counting can't free cycles, such as an instance stored in its
+/// own field or a closure captured by the environment it closes over. The
+/// interpreter's [`Heap`] finds and frees those with a tracing collection.
+#[derive(Clone)]
+pub enum Value {
     Nil,
     Bool(bool),
     Number(f64),
-    String(String),
+    String(Rc<str>),
+    Function(Rc<LoxFunction>),
+    NativeFunction(Rc<Native<Value>>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<RefCell<LoxInstance>>),
 }
 
 impl Display for Value {
@@ -17,111 +40,513 @@ impl Display for Value {
         match self {
             Value::Nil => write!(f, "nil"),
             Value::Bool(b) => write!(f, "{}", b),
-            Value::Number(n) => write!(f, "{}", n),
+            Value::Number(n) => f.write_str(&format_number(*n, NumberStyle::Value)),
             Value::String(s) => write!(f, "{}", s),
+            Value::Function(function) => write!(f, "{}", function),
+            Value::NativeFunction(function) => write!(f, "{}", function),
+            Value::Class(class) => write!(f, "{}", class),
+            Value::Instance(instance) => write!(f, "{}", instance.borrow()),
         }
     }
 }
 
-fn evaluate_expr(expr: &Expr) -> Result<Value, &'static str> {
-    match expr {
-        Expr::Number(t) => Ok(Value::Number(t.to_owned())),
-        Expr::String(s) => Ok(Value::String(s.to_owned())),
-        Expr::Bool(b) => Ok(Value::Bool(b.to_owned())),
-        Expr::Nil => Ok(Value::Nil),
-        Expr::Grouping(v) => evaluate_expr(v),
-        Expr::Unary { operator, right } => {
-            let res = evaluate_expr(right)?;
-            match operator.token_type {
-                TokenType::Minus => match res {
-                    Value::Number(n) => Ok(Value::Number(-n)),
-                    _ => Err("Operand must be a number."),
-                },
-                TokenType::Bang => match res {
-                    Value::Bool(b) => Ok(Value::Bool(!b)),
-                    Value::Nil => Ok(Value::Bool(true)),
-                    Value::Number(n) => Ok(Value::Bool(n == 0.0)),
-                    Value::String(s) => Ok(Value::Bool(s.is_empty())),
-                    // _ => Err("Unsupported value for Bang token"),
-                },
-                _ => Err("Unsupported token type for unary expression"),
+fn is_equality(operator: &Token) -> bool {
+    matches!(
+        operator.token_type,
+        TokenType::Operator {
+            op: Operator::EqualEqual | Operator::BangEqual,
+        }
+    )
+}
+
+impl LoxValue for Value {
+    fn kind(&self) -> Kind<'_> {
+        match self {
+            Value::Nil => Kind::Nil,
+            Value::Bool(b) => Kind::Bool(*b),
+            Value::Number(n) => Kind::Number(*n),
+            Value::String(s) => Kind::String(s),
+            Value::Function(function) => object(function),
+            Value::NativeFunction(function) => object(function),
+            Value::Class(class) => object(class),
+            Value::Instance(instance) => object(instance),
+        }
+    }
+
+    fn number(value: f64) -> Self {
+        Value::Number(value)
+    }
+}
+
+/// Why a statement stopped executing before reaching its end. `return` is
+/// threaded through the same channel as errors so it unwinds every enclosing
+/// block and loop up to the call that is waiting for it.
+enum Interrupt {
+    Error(RuntimeError),
+    Return(Value),
+}
+
+impl From<RuntimeError> for Interrupt {
+    fn from(error: RuntimeError) -> Self {
+        Interrupt::Error(error)
+    }
+}
+
+fn undefined_variable(name: &Token) -> RuntimeError {
+    RuntimeError::new(name, format!("Undefined variable '{}'.", name.lexeme))
+}
+
+/// Tree-walking interpreter. `environment` is the innermost scope currently
+/// executing; blocks swap in a child scope and restore it when they finish.
+/// Variables the resolver left unresolved are looked up in `globals`.
+pub struct Interpreter {
+    globals: Rc<RefCell<Environment>>,
+    environment: Rc<RefCell<Environment>>,
+    strings: Interner,
+    heap: Heap,
+    /// Lox functions currently executing.
+    call_depth: usize,
+}
+
+impl Interpreter {
+    pub fn new(options: GcOptions) -> Self {
+        let mut heap = Heap::new(options);
+        let mut globals = Environment::new();
+        for native in natives() {
+            globals.define(native.name, Value::NativeFunction(Rc::new(native)));
+        }
+        let globals = heap.environment(globals);
+        Self {
+            environment: Rc::clone(&globals),
+            globals,
+            strings: Interner::default(),
+            heap,
+            call_depth: 0,
+        }
+    }
+
+    /// Runs a collection if enough has been allocated since the last one.
+    /// In stress mode the heap also collects on every allocation, in the
+    /// middle of statements.
+    fn maybe_collect(&mut self) {
+        if self.heap.should_collect() {
+            self.heap.collect();
+            self.strings.sweep();
+        }
+    }
+
+    fn look_up_variable(&self, name: &Token, depth: &Depth) -> Result<Value, RuntimeError> {
+        let value = match depth.get() {
+            Some(distance) => self.environment.borrow().get_at(distance, &name.lexeme),
+            None => self.globals.borrow().get(&name.lexeme),
+        };
+        value.ok_or_else(|| undefined_variable(name))
+    }
+
+    fn call(
+        &mut self,
+        callee: Value,
+        arguments: Vec<Value>,
+        paren: &Token,
+    ) -> Result<Value, RuntimeError> {
+        let arity = match &callee {
+            Value::Function(function) => function.arity(),
+            Value::NativeFunction(function) => function.arity,
+            Value::Class(class) => class.arity(),
+            _ => {
+                return Err(RuntimeError::new(
+                    paren,
+                    "Can only call functions and classes.",
+                ))
+            }
+        };
+        if arguments.len() != arity {
+            return Err(RuntimeError::new(
+                paren,
+                format!("Expected {} arguments but got {}.", arity, arguments.len()),
+            ));
+        }
+
+        match callee {
+            Value::Function(function) => self.call_function(&function, arguments, paren),
+            Value::NativeFunction(function) => Ok((function.function)(&arguments)),
+            Value::Class(class) => {
+                let instance =
+                    Value::Instance(self.heap.instance(LoxInstance::new(Rc::clone(&class))));
+                if let Some(initializer) = class.find_method("init") {
+                    let initializer = initializer.bind(instance.clone(), &mut self.heap);
+                    self.call_function(&initializer, arguments, paren)?;
+                }
+                Ok(instance)
+            }
+            _ => unreachable!("non-callable values are rejected above"),
+        }
+    }
+
+    fn call_function(
+        &mut self,
+        function: &LoxFunction,
+        arguments: Vec<Value>,
+        paren: &Token,
+    ) -> Result<Value, RuntimeError> {
+        // Counted like the VM's call frames, where the script takes one.
+        if self.call_depth + 1 == FRAMES_MAX {
+            return Err(RuntimeError::new(paren, "Stack overflow."));
+        }
+        let mut scope = Environment::with_enclosing(Rc::clone(&function.closure));
+        for (param, argument) in function.declaration.params.iter().zip(arguments) {
+            scope.define(&param.lexeme, argument);
+        }
+        let scope = self.heap.environment(scope);
+        self.call_depth += 1;
+        let result = self.execute_block(&function.declaration.body, scope);
+        self.call_depth -= 1;
+        match result {
+            Err(Interrupt::Error(mut error)) => {
+                error.unwind(&function.declaration.name.lexeme, paren.line);
+                Err(error)
             }
+            _ if function.is_initializer => Ok(function
+                .closure
+                .borrow()
+                .get_at(0, "this")
+                .expect("initializer closures always bind `this`")),
+            Ok(()) => Ok(Value::Nil),
+            Err(Interrupt::Return(value)) => Ok(value),
         }
-        Expr::Binary {
-            operator,
-            left,
-            right,
-        } => {
-            let left = evaluate_expr(left)?;
-            let right = evaluate_expr(right)?;
-            match (left, right) {
-                (Value::Number(n), Value::Number(m)) => match operator.token_type {
-                    TokenType::Star => Ok(Value::Number(n * m)),
-                    TokenType::Slash => Ok(Value::Number(n / m)),
-                    TokenType::Plus => Ok(Value::Number(n + m)),
-                    TokenType::Minus => Ok(Value::Number(n - m)),
-                    TokenType::Greater => Ok(Value::Bool(n > m)),
-                    TokenType::Less => Ok(Value::Bool(n < m)),
-                    TokenType::Operator {
-                        op: Operator::LessEqual,
-                    } => Ok(Value::Bool(n <= m)),
-                    TokenType::Operator {
-                        op: Operator::GreaterEqual,
-                    } => Ok(Value::Bool(n >= m)),
-                    TokenType::Operator {
-                        op: Operator::EqualEqual,
-                    } => Ok(Value::Bool(n == m)),
-                    TokenType::Operator {
-                        op: Operator::BangEqual,
-                    } => Ok(Value::Bool(n != m)),
-                    _ => Err("Unsupported token type for binary expression on numbers"),
-                },
-                (Value::String(s), Value::String(t)) => match operator.token_type {
-                    TokenType::Plus => Ok(Value::String(s + t.as_str())),
-                    TokenType::Operator {
-                        op: Operator::EqualEqual,
-                    } => Ok(Value::Bool(s == t)),
-                    TokenType::Operator {
-                        op: Operator::BangEqual,
-                    } => Ok(Value::Bool(s != t)),
-                    _ => Err("Unsupported token type for binary expression on strings"),
-                },
-                (Value::Number(_), Value::String(_)) | (Value::String(_), Value::Number(_)) => {
-                    match operator.token_type {
+    }
+
+    fn evaluate(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        match expr {
+            Expr::Number(t, _) => Ok(Value::Number(t.to_owned())),
+            Expr::String(s, _) => Ok(Value::String(self.strings.intern(s))),
+            Expr::Bool(b, _) => Ok(Value::Bool(b.to_owned())),
+            Expr::Nil(_) => Ok(Value::Nil),
+            Expr::Grouping(v, _) => self.evaluate(v),
+            Expr::Get { object, name } => match self.evaluate(object)? {
+                Value::Instance(instance) => {
+                    if let Some(value) = instance.borrow().fields.get(&name.lexeme) {
+                        return Ok(value.clone());
+                    }
+                    let method = instance.borrow().class.find_method(&name.lexeme);
+                    match method {
+                        Some(method) => Ok(Value::Function(
+                            method.bind(Value::Instance(Rc::clone(&instance)), &mut self.heap),
+                        )),
+                        None => Err(RuntimeError::new(
+                            name,
+                            format!("Undefined property '{}'.", name.lexeme),
+                        )),
+                    }
+                }
+                _ => Err(RuntimeError::new(name, "Only instances have properties.")),
+            },
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => match self.evaluate(object)? {
+                Value::Instance(instance) => {
+                    let value = self.evaluate(value)?;
+                    instance
+                        .borrow_mut()
+                        .fields
+                        .insert(name.lexeme.clone(), value.clone());
+                    Ok(value)
+                }
+                _ => Err(RuntimeError::new(name, "Only instances have fields.")),
+            },
+            Expr::This { keyword, depth } => self.look_up_variable(keyword, depth),
+            Expr::Super {
+                keyword,
+                method,
+                depth,
+            } => {
+                // `this` always lives in the scope just inside the one
+                // holding `super`.
+                let distance = depth.get().unwrap_or_default();
+                let superclass = self.look_up_variable(keyword, depth)?;
+                let instance = self
+                    .environment
+                    .borrow()
+                    .get_at(distance.saturating_sub(1), "this")
+                    .ok_or_else(|| undefined_variable(keyword))?;
+                let found = match &superclass {
+                    Value::Class(superclass) => superclass.find_method(&method.lexeme),
+                    _ => None,
+                };
+                match found {
+                    Some(found) => Ok(Value::Function(found.bind(instance, &mut self.heap))),
+                    None => Err(RuntimeError::new(
+                        method,
+                        format!("Undefined property '{}'.", method.lexeme),
+                    )),
+                }
+            }
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+            } => {
+                let callee = self.evaluate(callee)?;
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.evaluate(argument))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.call(callee, arguments, paren)
+            }
+            Expr::Variable { name, depth } => self.look_up_variable(name, depth),
+            Expr::Assign { name, value, depth } => {
+                let value = self.evaluate(value)?;
+                let assigned = match depth.get() {
+                    Some(distance) => self.environment.borrow_mut().assign_at(
+                        distance,
+                        &name.lexeme,
+                        value.clone(),
+                    ),
+                    None => self
+                        .globals
+                        .borrow_mut()
+                        .assign(&name.lexeme, value.clone()),
+                };
+                if !assigned {
+                    return Err(undefined_variable(name));
+                }
+                Ok(value)
+            }
+            Expr::Logical {
+                operator,
+                left,
+                right,
+            } => {
+                let left = self.evaluate(left)?;
+                let short_circuits = match operator.token_type {
+                    TokenType::Keyword {
+                        kw: Keyword::Or, ..
+                    } => left.is_truthy(),
+                    _ => !left.is_truthy(),
+                };
+                if short_circuits {
+                    Ok(left)
+                } else {
+                    self.evaluate(right)
+                }
+            }
+            Expr::Unary { operator, right } => {
+                let res = self.evaluate(right)?;
+                let result = match operator.token_type {
+                    TokenType::Minus => match res {
+                        Value::Number(n) => Ok(Value::Number(-n)),
+                        _ => Err(RuntimeError::new(operator, "Operand must be a number.")),
+                    },
+                    TokenType::Bang => Ok(Value::Bool(!res.is_truthy())),
+                    _ => Err(RuntimeError::new(
+                        operator,
+                        "Unsupported token type for unary expression",
+                    )),
+                };
+                result.map_err(|error| error.at(expr.span()))
+            }
+            Expr::Binary {
+                operator,
+                left,
+                right,
+            } => {
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
+                let result = match (left, right) {
+                    (left, right) if is_equality(operator) => {
+                        let equal = left.is_equal(&right);
+                        Ok(Value::Bool(match operator.token_type {
+                            TokenType::Operator {
+                                op: Operator::EqualEqual,
+                            } => equal,
+                            _ => !equal,
+                        }))
+                    }
+                    (Value::Number(n), Value::Number(m)) => match operator.token_type {
+                        TokenType::Star => Ok(Value::Number(n * m)),
+                        TokenType::Slash => Ok(Value::Number(n / m)),
+                        TokenType::Plus => Ok(Value::Number(n + m)),
+                        TokenType::Minus => Ok(Value::Number(n - m)),
+                        TokenType::Greater => Ok(Value::Bool(n > m)),
+                        TokenType::Less => Ok(Value::Bool(n < m)),
                         TokenType::Operator {
-                            op: Operator::EqualEqual,
-                        } => Ok(Value::Bool(false)),
+                            op: Operator::LessEqual,
+                        } => Ok(Value::Bool(n <= m)),
                         TokenType::Operator {
-                            op: Operator::BangEqual,
-                        } => Ok(Value::Bool(true)),
-                        TokenType::Star | TokenType::Slash => Err("Operands must be numbers."),
+                            op: Operator::GreaterEqual,
+                        } => Ok(Value::Bool(n >= m)),
+                        _ => Err(RuntimeError::new(
+                            operator,
+                            "Unsupported token type for binary expression on numbers",
+                        )),
+                    },
+                    (Value::String(s), Value::String(t))
+                        if matches!(operator.token_type, TokenType::Plus) =>
+                    {
+                        Ok(Value::String(self.strings.intern(&format!("{s}{t}"))))
+                    }
+                    _ if matches!(operator.token_type, TokenType::Plus) => Err(RuntimeError::new(
+                        operator,
+                        "Operands must be two numbers or two strings.",
+                    )),
+                    _ => Err(RuntimeError::new(operator, "Operands must be numbers.")),
+                };
+                result.map_err(|error| error.at(expr.span()))
+            } // _ => Err("Unsupported expression type"),
+        }
+    }
+
+    fn execute(&mut self, stmt: &Stmt) -> Result<(), Interrupt> {
+        self.maybe_collect();
+        match stmt {
+            Stmt::Expression(expr) => {
+                self.evaluate(expr)?;
+            }
+            Stmt::Print { value, .. } => println!("{}", self.evaluate(value)?),
+            Stmt::Var {
+                name, initializer, ..
+            } => {
+                let value = match initializer {
+                    Some(expr) => self.evaluate(expr)?,
+                    None => Value::Nil,
+                };
+                self.environment.borrow_mut().define(&name.lexeme, value);
+            }
+            Stmt::Block(statements) => {
+                let scope = Environment::with_enclosing(Rc::clone(&self.environment));
+                let scope = self.heap.environment(scope);
+                self.execute_block(statements, scope)?;
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                if self.evaluate(condition)?.is_truthy() {
+                    self.execute(then_branch)?;
+                } else if let Some(else_branch) = else_branch {
+                    self.execute(else_branch)?;
+                }
+            }
+            Stmt::While { condition, body } => {
+                while self.evaluate(condition)?.is_truthy() {
+                    self.execute(body)?;
+                }
+            }
+            Stmt::Function(declaration) => {
+                let function = LoxFunction {
+                    declaration: Rc::clone(declaration),
+                    closure: Rc::clone(&self.environment),
+                    is_initializer: false,
+                };
+                let function = self.heap.function(function);
+                self.environment
+                    .borrow_mut()
+                    .define(&declaration.name.lexeme, Value::Function(function));
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+                ..
+            } => {
+                let superclass = match superclass {
+                    Some(expr @ Expr::Variable { name, .. }) => match self.evaluate(expr)? {
+                        Value::Class(class) => Some(class),
                         _ => {
-                            Err("Unsupported token type for binary expression on string and number")
+                            return Err(
+                                RuntimeError::new(name, "Superclass must be a class.").into()
+                            )
                         }
+                    },
+                    _ => None,
+                };
+                self.environment
+                    .borrow_mut()
+                    .define(&name.lexeme, Value::Nil);
+
+                // Methods of a subclass close over an extra scope holding
+                // `super`, sitting between the class's scope and `this`.
+                let closure = match &superclass {
+                    Some(superclass) => {
+                        let mut scope = Environment::with_enclosing(Rc::clone(&self.environment));
+                        scope.define("super", Value::Class(Rc::clone(superclass)));
+                        self.heap.environment(scope)
                     }
-                }
-                (_, _) => match operator.token_type {
-                    TokenType::Star | TokenType::Slash => Err("Operands must be numbers."),
-                    _ => Err("Unsupported"),
-                },
+                    None => Rc::clone(&self.environment),
+                };
+                let methods = methods
+                    .iter()
+                    .map(|method| {
+                        let function = LoxFunction {
+                            declaration: Rc::clone(method),
+                            closure: Rc::clone(&closure),
+                            is_initializer: method.name.lexeme == "init",
+                        };
+                        (method.name.lexeme.clone(), self.heap.function(function))
+                    })
+                    .collect::<HashMap<_, _>>();
+
+                let class = LoxClass {
+                    name: name.lexeme.clone(),
+                    superclass,
+                    methods,
+                };
+                let class = self.heap.class(class);
+                self.environment
+                    .borrow_mut()
+                    .define(&name.lexeme, Value::Class(class));
+            }
+            Stmt::Return { value, .. } => {
+                let value = match value {
+                    Some(expr) => self.evaluate(expr)?,
+                    None => Value::Nil,
+                };
+                return Err(Interrupt::Return(value));
             }
-        } // _ => Err("Unsupported expression type"),
+        }
+        Ok(())
+    }
+
+    /// Runs `statements` inside `scope`, restoring the current environment
+    /// afterwards even when a statement fails.
+    fn execute_block(
+        &mut self,
+        statements: &[Stmt],
+        scope: Rc<RefCell<Environment>>,
+    ) -> Result<(), Interrupt> {
+        let previous = std::mem::replace(&mut self.environment, scope);
+        let result = statements
+            .iter()
+            .try_for_each(|statement| self.execute(statement));
+        self.environment = previous;
+        result
     }
 }
 
-pub fn evaluate_exprs(exprs: Vec<Option<Expr>>) {
-    for expr in exprs {
-        if let Some(expr_v) = expr {
-            let evaluated = evaluate_expr(&expr_v);
-            evaluated
-                .and_then(|value| {
-                    println!("{value}");
-                    Ok(())
-                })
-                .unwrap_or_else(|e| {
-                    writeln!(io::stderr(), "{}", e).unwrap();
-                    process::exit(70)
-                });
-        }
+pub fn evaluate_exprs(exprs: Vec<Expr>) -> Result<(), RuntimeError> {
+    let mut interpreter = Interpreter::new(GcOptions::default());
+    for expr_v in exprs {
+        println!("{}", interpreter.evaluate(&expr_v)?);
+    }
+    Ok(())
+}
+
+pub fn run_program(stmts: Vec<Stmt>, options: GcOptions) -> Result<(), RuntimeError> {
+    let mut interpreter = Interpreter::new(options);
+    let result = stmts
+        .iter()
+        .try_for_each(|stmt| match interpreter.execute(stmt) {
+            Ok(()) => Ok(()),
+            Err(Interrupt::Error(error)) => Err(error),
+            // The resolver rejects `return` outside of a function.
+            Err(Interrupt::Return(_)) => Ok(()),
+        });
+    if options.stats {
+        interpreter.heap.stats.strings_freed = interpreter.strings.freed;
+        interpreter.heap.print_stats();
     }
+    result
 }
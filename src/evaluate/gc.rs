@@ -0,0 +1,304 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+use super::callable::LoxFunction;
+use super::class::{LoxClass, LoxInstance};
+use super::environment::Environment;
+use super::evaluate::Value;
+
+/// Objects tracked before the first collection; after each one the
+/// threshold grows to twice the number of objects still alive.
+const INITIAL_THRESHOLD: usize = 1024;
+
+/// Garbage collector settings, chosen on the command line.
+#[derive(Clone, Copy, Default)]
+pub struct GcOptions {
+    /// Collect on every allocation, to shake out objects the collector
+    /// fails to account for.
+    pub stress: bool,
+    /// Print [`GcStats`] to stderr when the program ends.
+    pub stats: bool,
+}
+
+#[derive(Default)]
+pub struct GcStats {
+    pub collections: usize,
+    /// Collections given up because an object was mutably borrowed, so its
+    /// references couldn't be traced.
+    pub skipped_collections: usize,
+    pub allocated: usize,
+    /// Objects whose last reference went away on its own.
+    pub freed_by_refcount: usize,
+    /// Objects only kept alive by a reference cycle, freed by a collection.
+    pub freed_by_collector: usize,
+    pub strings_freed: usize,
+}
+
+/// Identity of a tracked object: the address of its allocation.
+type Address = usize;
+
+fn address<T: ?Sized>(pointer: *const T) -> Address {
+    pointer as *const () as Address
+}
+
+fn value_address(value: &Value) -> Option<Address> {
+    match value {
+        Value::Function(function) => Some(address(Rc::as_ptr(function))),
+        Value::Class(class) => Some(address(Rc::as_ptr(class))),
+        Value::Instance(instance) => Some(address(Rc::as_ptr(instance))),
+        Value::Nil
+        | Value::Bool(_)
+        | Value::Number(_)
+        | Value::String(_)
+        | Value::NativeFunction(_) => None,
+    }
+}
+
+/// The kinds of object that can take part in a reference cycle.
+enum HeapObject {
+    Environment(Weak<RefCell<Environment>>),
+    Function(Weak<LoxFunction>),
+    Class(Weak<LoxClass>),
+    Instance(Weak<RefCell<LoxInstance>>),
+}
+
+impl HeapObject {
+    fn address(&self) -> Address {
+        match self {
+            HeapObject::Environment(weak) => address(weak.as_ptr()),
+            HeapObject::Function(weak) => address(weak.as_ptr()),
+            HeapObject::Class(weak) => address(weak.as_ptr()),
+            HeapObject::Instance(weak) => address(weak.as_ptr()),
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            HeapObject::Environment(weak) => weak.strong_count(),
+            HeapObject::Function(weak) => weak.strong_count(),
+            HeapObject::Class(weak) => weak.strong_count(),
+            HeapObject::Instance(weak) => weak.strong_count(),
+        }
+    }
+
+    /// The objects this one holds a strong reference to, one entry per
+    /// reference. `None` if the object is mutably borrowed, meaning the
+    /// interpreter is in the middle of changing it.
+    fn children(&self) -> Option<Vec<Address>> {
+        let mut children = Vec::new();
+        match self {
+            HeapObject::Environment(weak) => {
+                if let Some(environment) = weak.upgrade() {
+                    let environment = environment.try_borrow().ok()?;
+                    children.extend(environment.values().filter_map(value_address));
+                    children.extend(
+                        environment
+                            .enclosing()
+                            .map(|enclosing| address(Rc::as_ptr(enclosing))),
+                    );
+                }
+            }
+            HeapObject::Function(weak) => {
+                if let Some(function) = weak.upgrade() {
+                    children.push(address(Rc::as_ptr(&function.closure)));
+                }
+            }
+            HeapObject::Class(weak) => {
+                if let Some(class) = weak.upgrade() {
+                    children.extend(
+                        class
+                            .superclass
+                            .iter()
+                            .map(|superclass| address(Rc::as_ptr(superclass))),
+                    );
+                    children.extend(
+                        class
+                            .methods
+                            .values()
+                            .map(|method| address(Rc::as_ptr(method))),
+                    );
+                }
+            }
+            HeapObject::Instance(weak) => {
+                if let Some(instance) = weak.upgrade() {
+                    let instance = instance.try_borrow().ok()?;
+                    children.push(address(Rc::as_ptr(&instance.class)));
+                    children.extend(instance.fields.values().filter_map(value_address));
+                }
+            }
+        }
+        Some(children)
+    }
+
+    /// Drops everything the object refers to, breaking any cycle through it.
+    /// Only environments and instances are mutable, and every cycle passes
+    /// through one of them.
+    fn clear(&self) {
+        match self {
+            HeapObject::Environment(weak) => {
+                if let Some(environment) = weak.upgrade() {
+                    // Taken out first so nothing is dropped mid-borrow.
+                    let contents = std::mem::take(&mut *environment.borrow_mut());
+                    drop(contents);
+                }
+            }
+            HeapObject::Instance(weak) => {
+                if let Some(instance) = weak.upgrade() {
+                    let fields = std::mem::take(&mut instance.borrow_mut().fields);
+                    drop(fields);
+                }
+            }
+            HeapObject::Function(_) | HeapObject::Class(_) => {}
+        }
+    }
+}
+
+/// Every environment, function, class and instance the interpreter creates
+/// is allocated here. Objects are still reference counted, which frees most
+/// of them promptly; the heap keeps a weak reference to each so a tracing
+/// collection can find the cycles reference counting leaks.
+///
+/// A collection works out the roots without being told them: any reference
+/// to an object that doesn't come from another tracked object must come from
+/// the environment chain, the Rust call stack of the interpreter (saved
+/// environments, arguments being evaluated, values being returned) or the
+/// globals. Objects reachable from those roots are marked; the rest are
+/// garbage, and clearing them breaks their cycles so the counts drop to zero.
+pub struct Heap {
+    objects: Vec<HeapObject>,
+    next_gc: usize,
+    options: GcOptions,
+    pub stats: GcStats,
+}
+
+impl Heap {
+    pub fn new(options: GcOptions) -> Self {
+        Self {
+            objects: Vec::new(),
+            next_gc: INITIAL_THRESHOLD,
+            options,
+            stats: GcStats::default(),
+        }
+    }
+
+    /// The caller still holds the new object, so a stress collection here
+    /// sees it as a root.
+    fn track(&mut self, object: HeapObject) {
+        self.objects.push(object);
+        self.stats.allocated += 1;
+        if self.options.stress {
+            self.collect();
+        }
+    }
+
+    pub fn environment(&mut self, environment: Environment) -> Rc<RefCell<Environment>> {
+        let environment = Rc::new(RefCell::new(environment));
+        self.track(HeapObject::Environment(Rc::downgrade(&environment)));
+        environment
+    }
+
+    pub fn function(&mut self, function: LoxFunction) -> Rc<LoxFunction> {
+        let function = Rc::new(function);
+        self.track(HeapObject::Function(Rc::downgrade(&function)));
+        function
+    }
+
+    pub fn class(&mut self, class: LoxClass) -> Rc<LoxClass> {
+        let class = Rc::new(class);
+        self.track(HeapObject::Class(Rc::downgrade(&class)));
+        class
+    }
+
+    pub fn instance(&mut self, instance: LoxInstance) -> Rc<RefCell<LoxInstance>> {
+        let instance = Rc::new(RefCell::new(instance));
+        self.track(HeapObject::Instance(Rc::downgrade(&instance)));
+        instance
+    }
+
+    pub fn should_collect(&self) -> bool {
+        self.objects.len() >= self.next_gc
+    }
+
+    /// Forgets objects reference counting has already freed.
+    fn prune(&mut self) {
+        let before = self.objects.len();
+        self.objects.retain(|object| object.strong_count() > 0);
+        self.stats.freed_by_refcount += before - self.objects.len();
+    }
+
+    pub fn collect(&mut self) {
+        self.prune();
+
+        let index: HashMap<Address, usize> = self
+            .objects
+            .iter()
+            .enumerate()
+            .map(|(i, object)| (object.address(), i))
+            .collect();
+        // Start from each object's reference count and subtract the
+        // references held by other tracked objects: what remains comes from
+        // outside the heap, making the object a root.
+        let mut outside_references: Vec<usize> =
+            self.objects.iter().map(HeapObject::strong_count).collect();
+        let mut edges = Vec::with_capacity(self.objects.len());
+        for object in &self.objects {
+            let Some(children) = object.children() else {
+                self.stats.skipped_collections += 1;
+                return;
+            };
+            let children: Vec<usize> = children
+                .iter()
+                .filter_map(|child| index.get(child).copied())
+                .collect();
+            for &child in &children {
+                outside_references[child] = outside_references[child].saturating_sub(1);
+            }
+            edges.push(children);
+        }
+
+        let mut marked = vec![false; self.objects.len()];
+        let mut pending: Vec<usize> = (0..self.objects.len())
+            .filter(|&i| outside_references[i] > 0)
+            .collect();
+        while let Some(i) = pending.pop() {
+            if !marked[i] {
+                marked[i] = true;
+                pending.extend(&edges[i]);
+            }
+        }
+
+        for (object, _) in self
+            .objects
+            .iter()
+            .zip(&marked)
+            .filter(|(_, &marked)| !marked)
+        {
+            object.clear();
+        }
+        // Clearing the garbage breaks its cycles, so its counts reach zero.
+        let tracked = self.objects.len();
+        self.objects.retain(|object| object.strong_count() > 0);
+
+        self.stats.collections += 1;
+        self.stats.freed_by_collector += tracked - self.objects.len();
+        self.next_gc = (self.objects.len() * 2).max(INITIAL_THRESHOLD);
+    }
+
+    /// Prints the statistics to stderr, with the number of objects still
+    /// alive.
+    pub fn print_stats(&mut self) {
+        self.prune();
+        let stats = &self.stats;
+        eprintln!("[gc] collections: {}", stats.collections);
+        eprintln!("[gc] collections skipped: {}", stats.skipped_collections);
+        eprintln!("[gc] objects allocated: {}", stats.allocated);
+        eprintln!(
+            "[gc] freed by reference counting: {}",
+            stats.freed_by_refcount
+        );
+        eprintln!("[gc] freed by collection: {}", stats.freed_by_collector);
+        eprintln!("[gc] strings freed: {}", stats.strings_freed);
+        eprintln!("[gc] objects alive: {}", self.objects.len());
+    }
+}
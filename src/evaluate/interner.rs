@@ -0,0 +1,76 @@
+use std::collections::HashSet;
+use std::rc::Rc;
+
+/// Bytes of string data interned before the first sweep; after each one the
+/// threshold grows to twice the size of the strings still in use.
+const INITIAL_THRESHOLD: usize = 1 << 20;
+
+/// Keeps one shared copy of every distinct string the program creates, so
+/// equal strings are the same allocation and compare by pointer. Evaluating
+/// a string literal again hands out the existing copy instead of allocating.
+///
+/// Strings the program has let go of are dropped by sweeping, which happens
+/// whenever enough new string data has been interned, so a loop building up
+/// a string doesn't keep every intermediate copy alive.
+pub struct Interner {
+    strings: HashSet<Rc<str>>,
+    /// Total length of the interned strings.
+    bytes: usize,
+    next_sweep: usize,
+    /// How many strings sweeps have dropped so far.
+    pub freed: usize,
+}
+
+impl Default for Interner {
+    fn default() -> Self {
+        Self {
+            strings: HashSet::new(),
+            bytes: 0,
+            next_sweep: INITIAL_THRESHOLD,
+            freed: 0,
+        }
+    }
+}
+
+impl Interner {
+    pub fn intern(&mut self, string: &str) -> Rc<str> {
+        if let Some(interned) = self.strings.get(string) {
+            return Rc::clone(interned);
+        }
+        if self.bytes >= self.next_sweep {
+            self.sweep();
+        }
+        let interned: Rc<str> = Rc::from(string);
+        self.strings.insert(Rc::clone(&interned));
+        self.bytes += string.len();
+        interned
+    }
+
+    /// Drops strings nothing but the interner refers to any more.
+    pub fn sweep(&mut self) {
+        let before = self.strings.len();
+        self.strings.retain(|string| Rc::strong_count(string) > 1);
+        self.freed += before - self.strings.len();
+        self.bytes = self.strings.iter().map(|string| string.len()).sum();
+        self.next_sweep = (self.bytes * 2).max(INITIAL_THRESHOLD);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sweeps_dropped_strings_as_new_ones_are_interned() {
+        let mut strings = Interner::default();
+        let kept = strings.intern("kept");
+        let mut built = String::new();
+        for _ in 0..2000 {
+            built.push_str("xxxxxxxxxx");
+            strings.intern(&built);
+        }
+        assert!(strings.freed > 0);
+        assert!(strings.bytes < 4 * INITIAL_THRESHOLD);
+        assert!(Rc::ptr_eq(&kept, &strings.intern("kept")));
+    }
+}
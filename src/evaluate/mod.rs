@@ -1 +1,7 @@
+pub mod callable;
+pub mod class;
+pub mod environment;
+#[allow(clippy::module_inception)]
 pub mod evaluate;
+pub mod gc;
+pub mod interner;
@@ -1,13 +1,20 @@
+use std::cell::Cell;
 use std::fmt::Display;
 
-use crate::tokens::Token;
+use crate::tokens::{Span, Token};
+use crate::utils::{format_number, NumberStyle};
+
+/// Number of scopes between a variable's use and its declaration, filled in
+/// by the resolver. `None` means the variable is global.
+pub type Depth = Cell<Option<usize>>;
 
 #[derive(Clone)]
 pub enum Expr {
-    Bool(bool),
-    Nil,
-    Number(f64),
-    String(String),
+    /// Literals keep the token they were parsed from for its location.
+    Bool(bool, Token),
+    Nil(Token),
+    Number(f64, Token),
+    String(String, Token),
     Unary {
         operator: Token,
         right: Box<Expr>,
@@ -17,16 +24,80 @@ pub enum Expr {
         left: Box<Expr>,
         right: Box<Expr>,
     },
-    Grouping(Box<Expr>),
+    Logical {
+        operator: Token,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    Call {
+        callee: Box<Expr>,
+        paren: Token,
+        arguments: Vec<Expr>,
+    },
+    Get {
+        object: Box<Expr>,
+        name: Token,
+    },
+    Set {
+        object: Box<Expr>,
+        name: Token,
+        value: Box<Expr>,
+    },
+    This {
+        keyword: Token,
+        depth: Depth,
+    },
+    Super {
+        keyword: Token,
+        method: Token,
+        depth: Depth,
+    },
+    /// The span includes the parentheses.
+    Grouping(Box<Expr>, Span),
+    Variable {
+        name: Token,
+        depth: Depth,
+    },
+    Assign {
+        name: Token,
+        value: Box<Expr>,
+        depth: Depth,
+    },
+}
+
+impl Expr {
+    /// The source range this expression was parsed from.
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Bool(_, token)
+            | Expr::Nil(token)
+            | Expr::Number(_, token)
+            | Expr::String(_, token) => token.span,
+            Expr::Grouping(_, span) => *span,
+            Expr::Unary { operator, right } => operator.span.to(right.span()),
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                left.span().to(right.span())
+            }
+            Expr::Call { callee, paren, .. } => callee.span().to(paren.span),
+            Expr::Get { object, name } => object.span().to(name.span),
+            Expr::Set { object, value, .. } => object.span().to(value.span()),
+            Expr::This { keyword, .. } => keyword.span,
+            Expr::Super {
+                keyword, method, ..
+            } => keyword.span.to(method.span),
+            Expr::Variable { name, .. } => name.span,
+            Expr::Assign { name, value, .. } => name.span.to(value.span()),
+        }
+    }
 }
 
 impl Display for Expr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Expr::Bool(b) => f.write_fmt(format_args!("{b}")),
-            Expr::Nil => f.write_str("nil"),
-            Expr::Number(n) => f.write_fmt(format_args!("{n:?}")),
-            Expr::String(s) => f.write_fmt(format_args!("{}", s)),
+            Expr::Bool(b, _) => f.write_fmt(format_args!("{b}")),
+            Expr::Nil(_) => f.write_str("nil"),
+            Expr::Number(n, _) => f.write_str(&format_number(*n, NumberStyle::Literal)),
+            Expr::String(s, _) => f.write_fmt(format_args!("{}", s)),
             Expr::Unary { operator, right } => {
                 f.write_fmt(format_args!("({} {right})", operator.lexeme))
             }
@@ -34,19 +105,43 @@ impl Display for Expr {
                 operator,
                 left,
                 right,
+            }
+            | Expr::Logical {
+                operator,
+                left,
+                right,
             } => f.write_fmt(format_args!("({} {left} {right})", operator.lexeme)),
-            Expr::Grouping(expr) => f.write_fmt(format_args!("(group {})", expr)),
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                f.write_fmt(format_args!("(call {callee}"))?;
+                for argument in arguments {
+                    f.write_fmt(format_args!(" {argument}"))?;
+                }
+                f.write_str(")")
+            }
+            Expr::Get { object, name } => f.write_fmt(format_args!("(. {object} {})", name.lexeme)),
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => f.write_fmt(format_args!("(= (. {object} {}) {value})", name.lexeme)),
+            Expr::This { .. } => f.write_str("this"),
+            Expr::Super { method, .. } => f.write_fmt(format_args!("(super {})", method.lexeme)),
+            Expr::Grouping(expr, _) => f.write_fmt(format_args!("(group {})", expr)),
+            Expr::Variable { name, .. } => f.write_str(&name.lexeme),
+            Expr::Assign { name, value, .. } => {
+                f.write_fmt(format_args!("(= {} {value})", name.lexeme))
+            }
         }
     }
 }
 
-pub fn print_exprs(exprs: &Vec<Option<Expr>>) {
-    for expr in exprs {
-        if let Some(val) = expr {
-            match val {
-                Expr::String(s) => println!("{s}"),
-                _ => println!("{val}"),
-            }
+pub fn print_exprs(exprs: &[Expr]) {
+    for val in exprs {
+        match val {
+            Expr::String(s, _) => println!("{s}"),
+            _ => println!("{val}"),
         }
     }
 }
@@ -1,56 +1,284 @@
+mod cst;
+mod diagnostics;
+mod errors;
 mod evaluate;
 mod exprs;
 mod parser;
+mod resolver;
 mod scanner;
+mod stmts;
 mod tokens;
 mod utils;
+mod values;
+mod vm;
+use cst::{build_cst, print_cst};
+use diagnostics::{ErrorFormat, Reporter, ToDiagnostic};
+use errors::{ParseError, RuntimeError};
 use exprs::print_exprs;
-use parser::parser::parse_tokens;
-use scanner::tokenize::{print_tokens, scanner};
+use parser::parser::{parse_program, parse_tokens};
+use resolver::resolver::resolve_program;
+use scanner::json::{print_tokens_json, print_tokens_ndjson};
+use scanner::tokenize::{print_tokens, scanner, scanner_lossless, Scanner};
 use std::env;
+use std::fmt::Display;
 use std::fs;
-use std::io::{self, Write};
+use std::process;
+use std::thread;
+use stmts::Stmt;
+use tokens::Token;
 
-use self::evaluate::evaluate::evaluate_exprs;
+use self::evaluate::evaluate::{evaluate_exprs, run_program};
+use self::evaluate::gc::GcOptions;
+use self::evaluate::interner::Interner;
+use self::vm::compiler::compile;
+use self::vm::disassemble::disassemble_function;
+use self::vm::vm::run_script;
 
-fn get_file_content(filename: &String) -> String {
-    fs::read_to_string(filename).unwrap_or_else(|_| {
-        writeln!(io::stderr(), "Failed to read file {}", filename).unwrap();
-        String::new()
+/// Reads the source file, exiting with 66 (`EX_NOINPUT`) if it can't be
+/// read.
+fn get_file_content(filename: &str) -> String {
+    fs::read_to_string(filename).unwrap_or_else(|error| {
+        eprintln!("Failed to read file {filename}: {error}");
+        process::exit(66);
     })
 }
 
+fn print_usage(program: &str) {
+    eprintln!(
+        "Usage: {program} <command> <filename> [options]
+
+Commands:
+  tokenize     print the tokens         [--format text|json|ndjson]
+  parse        print expressions as a syntax tree
+  evaluate     evaluate expressions
+  run          run a program            [--backend tree|vm]
+                                        [--trace] (vm only)
+                                        [--gc-stress] [--gc-stats] (tree only)
+  disassemble  print the bytecode the vm backend runs
+  cst          print the concrete syntax tree, trivia included
+
+Every command also takes [--error-format plain|rich]."
+    );
+}
+
+/// The options `command` accepts, each with whether it takes a value, or
+/// `None` for an unknown command.
+fn command_options(command: &str) -> Option<&'static [(&'static str, bool)]> {
+    const ERROR_FORMAT: (&str, bool) = ("--error-format", true);
+    match command {
+        "tokenize" => Some(&[ERROR_FORMAT, ("--format", true)]),
+        "parse" | "evaluate" | "disassemble" | "cst" => Some(&[ERROR_FORMAT]),
+        "run" => Some(&[
+            ERROR_FORMAT,
+            ("--backend", true),
+            ("--trace", false),
+            ("--gc-stress", false),
+            ("--gc-stats", false),
+        ]),
+        _ => None,
+    }
+}
+
+/// Checks the trailing options against the ones a command accepts, so a
+/// misspelt flag is an error rather than silently ignored.
+fn check_options(options: &[String], accepted: &[(&str, bool)]) -> Result<(), String> {
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match accepted.iter().find(|(name, _)| name == option) {
+            Some((_, true)) if options.next().is_none() => {
+                return Err(format!("Missing value for {option}"))
+            }
+            Some(_) => {}
+            None => return Err(format!("Unknown option: {option}")),
+        }
+    }
+    Ok(())
+}
+
+/// Returns the value following `flag` among the trailing options, if any.
+fn flag_value<'a>(options: &'a [String], flag: &str) -> Option<&'a str> {
+    options
+        .iter()
+        .position(|option| option == flag)
+        .and_then(|i| options.get(i + 1))
+        .map(String::as_str)
+}
+
+/// Whether `flag` appears among the trailing options.
+fn has_flag(options: &[String], flag: &str) -> bool {
+    options.iter().any(|option| option == flag)
+}
+
+/// Reports compile-time (scan, parse or resolve) errors and exits with 65.
+fn exit_with_errors<E: Display + ToDiagnostic, T>(reporter: &Reporter, errors: Vec<E>) -> T {
+    for error in errors {
+        reporter.report(&error);
+    }
+    process::exit(65);
+}
+
+fn exit_with_runtime_error(reporter: &Reporter, error: RuntimeError) {
+    reporter.report(&error);
+    process::exit(70);
+}
+
+/// Runs `parse` over `source`, scanning lazily as the parser pulls tokens.
+/// Scan errors are reported in preference to parse errors, which are often
+/// just a knock-on effect of the input the scanner skipped.
+fn scan_and_parse<T>(
+    reporter: &Reporter,
+    source: &str,
+    parse: fn(&mut dyn Iterator<Item = Token>) -> Result<T, Vec<ParseError>>,
+) -> T {
+    let mut scan_errors = Vec::new();
+    let parsed = parse(
+        &mut Scanner::new(source)
+            .filter_map(|result| result.map_err(|error| scan_errors.push(error)).ok()),
+    );
+    if !scan_errors.is_empty() {
+        exit_with_errors(reporter, scan_errors)
+    }
+    parsed.unwrap_or_else(|errors| exit_with_errors(reporter, errors))
+}
+
+/// Parses and resolves a whole program, exiting on any error.
+fn parse_and_resolve(reporter: &Reporter, source: &str) -> Vec<Stmt> {
+    let stmts = scan_and_parse(reporter, source, parse_program);
+    resolve_program(&stmts).unwrap_or_else(|errors| exit_with_errors(reporter, errors));
+    stmts
+}
+
+/// The tree walker recurses on the native stack for every Lox call, so it
+/// needs far more than the main thread's default to reach the call depth
+/// limit. Only the pages actually used are committed.
+const STACK_SIZE: usize = 1 << 30;
+
+/// Runs `command` on a thread with a [`STACK_SIZE`] stack, for the commands
+/// that run the tree walker.
+fn with_tree_walker_stack(command: impl FnOnce() + Send) {
+    let finished = thread::scope(|scope| {
+        thread::Builder::new()
+            .name("interpreter".to_string())
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, command)
+            .expect("failed to spawn the interpreter thread")
+            .join()
+    });
+    if finished.is_err() {
+        // The panic message has already been printed.
+        process::exit(101);
+    }
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
     if args.len() < 3 {
-        writeln!(io::stderr(), "Usage: {} tokenize <filename>", args[0]).unwrap();
-        return;
+        print_usage(&args[0]);
+        process::exit(64);
     }
 
     let command = &args[1];
     let filename = &args[2];
+    let options = &args[3..];
+    let Some(accepted) = command_options(command) else {
+        eprintln!("Unknown command: {command}");
+        print_usage(&args[0]);
+        process::exit(64);
+    };
+    if let Err(message) = check_options(options, accepted) {
+        eprintln!("{message}");
+        print_usage(&args[0]);
+        process::exit(64);
+    }
+
+    let format = ErrorFormat::detect(flag_value(options, "--error-format")).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        process::exit(64);
+    });
+    let source = get_file_content(filename);
+    let reporter = Reporter::new(filename, &source, format);
 
     match command.as_str() {
         "tokenize" => {
-            let file_contents = get_file_content(filename);
-            let tokens = scanner(file_contents);
-            print_tokens(&tokens)
-        }
-        "evaluate" => {
-            let file_contents = get_file_content(filename);
-            let tokens = scanner(file_contents);
-            let exprs = parse_tokens(&mut tokens.iter());
-            evaluate_exprs(exprs)
+            let had_errors = match flag_value(options, "--format") {
+                None | Some("text") => {
+                    let (tokens, errors) = scanner(&source);
+                    print_tokens(&tokens);
+                    if !errors.is_empty() {
+                        exit_with_errors(&reporter, errors)
+                    }
+                    false
+                }
+                // Errors are part of the structured output, so they aren't
+                // reported on stderr as well.
+                Some("json") => print_tokens_json(Scanner::new(&source)),
+                Some("ndjson") => print_tokens_ndjson(Scanner::new(&source)),
+                Some(other) => {
+                    eprintln!("Unknown token format: {other}");
+                    process::exit(64);
+                }
+            };
+            if had_errors {
+                process::exit(65);
+            }
         }
+        "evaluate" => with_tree_walker_stack(|| {
+            let exprs = scan_and_parse(&reporter, &source, parse_tokens);
+            evaluate_exprs(exprs).unwrap_or_else(|error| exit_with_runtime_error(&reporter, error))
+        }),
         "parse" => {
-            let file_contents = get_file_content(filename);
-            let tokens = scanner(file_contents);
-            let exprs = parse_tokens(&mut tokens.iter());
+            let exprs = scan_and_parse(&reporter, &source, parse_tokens);
             print_exprs(&exprs)
         }
-        _ => {
-            writeln!(io::stderr(), "Unknown command: {}", command).unwrap();
-            return;
+        "run" => {
+            let gc = GcOptions {
+                stress: has_flag(options, "--gc-stress"),
+                stats: has_flag(options, "--gc-stats"),
+            };
+            match flag_value(options, "--backend") {
+                None | Some("tree") => {
+                    if has_flag(options, "--trace") {
+                        eprintln!("--trace needs the vm backend");
+                        process::exit(64);
+                    }
+                    with_tree_walker_stack(|| {
+                        let stmts = parse_and_resolve(&reporter, &source);
+                        run_program(stmts, gc)
+                            .unwrap_or_else(|error| exit_with_runtime_error(&reporter, error))
+                    })
+                }
+                Some("vm") => {
+                    // The VM's objects are plain reference counted values.
+                    if gc.stress || gc.stats {
+                        eprintln!("--gc-stress and --gc-stats need the tree backend");
+                        process::exit(64);
+                    }
+                    let stmts = parse_and_resolve(&reporter, &source);
+                    let mut strings = Interner::default();
+                    let script = compile(&stmts, &mut strings)
+                        .unwrap_or_else(|errors| exit_with_errors(&reporter, errors));
+                    run_script(script, strings, has_flag(options, "--trace"))
+                        .unwrap_or_else(|error| exit_with_runtime_error(&reporter, error))
+                }
+                Some(other) => {
+                    eprintln!("Unknown backend: {other}");
+                    process::exit(64);
+                }
+            }
+        }
+        "disassemble" => {
+            let stmts = parse_and_resolve(&reporter, &source);
+            let script = compile(&stmts, &mut Interner::default())
+                .unwrap_or_else(|errors| exit_with_errors(&reporter, errors));
+            disassemble_function(&script);
+        }
+        "cst" => {
+            // Scan errors don't stop the tree being built: rejected input
+            // is kept as trivia so the file still round-trips.
+            let (tokens, _) = scanner_lossless(&source);
+            let tree = build_cst(tokens);
+            print_cst(&tree, 0)
         }
+        _ => unreachable!("unknown commands are rejected above"),
     }
 }
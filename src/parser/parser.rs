@@ -1,145 +1,936 @@
-use std::io::{self, Write};
 use std::iter::Peekable;
-use std::process;
+use std::rc::Rc;
 
-use crate::exprs::Expr;
-use crate::tokens::{Keyword, Operator, Token, TokenType};
-use crate::utils::trim_string;
+use crate::errors::ParseError;
+use crate::exprs::{Depth, Expr};
+use crate::stmts::{FunctionDecl, Stmt};
+use crate::tokens::{Keyword, Operator, Span, Token, TokenType};
+use crate::utils::{parse_number, trim_string};
 
-pub fn parse_tokens(tokens_iter: &mut std::slice::Iter<'_, Token>) -> Vec<Option<Expr>> {
+type ParseResult<T> = Result<T, ParseError>;
+
+/// Tokens are pulled from the scanner one at a time as the parser needs
+/// them, with one token of lookahead.
+type Tokens<'a> = Peekable<&'a mut dyn Iterator<Item = Token>>;
+
+pub fn parse_tokens(
+    tokens_iter: &mut dyn Iterator<Item = Token>,
+) -> Result<Vec<Expr>, Vec<ParseError>> {
     let mut expressions = Vec::new();
-    let mut tokens_peek = tokens_iter.to_owned().peekable();
-    while tokens_peek.peek().is_some() {
-        expressions.push(parse_expression(&mut tokens_peek));
+    let mut errors = Vec::new();
+    let mut tokens_peek = tokens_iter.peekable();
+    while !check(&mut tokens_peek, |t| matches!(t, TokenType::EOF)) {
+        match parse_expression(&mut tokens_peek) {
+            Ok(expr) => expressions.push(expr),
+            Err(error) => {
+                errors.push(error);
+                // `synchronize` stops in front of statement keywords, which
+                // no expression can start with, so step past the offending
+                // token first to guarantee progress.
+                tokens_peek.next();
+                synchronize(&mut tokens_peek);
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(expressions)
+    } else {
+        Err(errors)
     }
-    expressions
-}
-
-fn string_to_f64(s: &str) -> Result<f64, std::num::ParseFloatError> {
-    s.parse()
 }
 
-fn parse_expression(tokens_iter: &mut Peekable<std::slice::Iter<'_, Token>>) -> Option<Expr> {
-    parse_binary(tokens_iter)
+/// Parses a whole program. A syntax error abandons only the declaration it
+/// occurs in, even inside a block or class body: the parser skips ahead to
+/// the next statement boundary and keeps going, so every error in the file is
+/// reported at once.
+pub fn parse_program(
+    tokens_iter: &mut dyn Iterator<Item = Token>,
+) -> Result<Vec<Stmt>, Vec<ParseError>> {
+    let mut statements = Vec::new();
+    let mut errors = Vec::new();
+    let mut tokens_peek = tokens_iter.peekable();
+    while !check(&mut tokens_peek, |t| matches!(t, TokenType::EOF)) {
+        statements.extend(parse_declaration_or_recover(&mut tokens_peek, &mut errors));
+    }
+    if errors.is_empty() {
+        Ok(statements)
+    } else {
+        Err(errors)
+    }
 }
 
-fn parse_binary(tokens_iter: &mut Peekable<std::slice::Iter<'_, Token>>) -> Option<Expr> {
-    let mut left = parse_unary(tokens_iter)?;
+/// Discards tokens until just after a `;` or just before a keyword that
+/// starts a statement, where parsing can sensibly resume.
+fn synchronize(tokens_iter: &mut Tokens<'_>) {
     while let Some(token) = tokens_iter.peek() {
         match &token.token_type {
-            TokenType::Slash
-            | TokenType::Star
-            | TokenType::Plus
-            | TokenType::Minus
-            | TokenType::Greater
-            | TokenType::Less
-            | TokenType::Operator {
-                op: Operator::LessEqual,
-            }
-            | TokenType::Operator {
-                op: Operator::GreaterEqual,
-            }
-            | TokenType::Operator {
-                op: Operator::EqualEqual,
+            TokenType::EOF => return,
+            TokenType::Semicolon => {
+                tokens_iter.next();
+                return;
             }
-            | TokenType::Operator {
-                op: Operator::BangEqual,
-            } => {
-                let consumed_token = tokens_iter.next()?;
-                let right = parse_unary(tokens_iter)?;
-                left = Expr::Binary {
-                    operator: consumed_token.clone(),
-                    left: Box::new(left),
-                    right: Box::new(right),
-                };
+            TokenType::Keyword {
+                kw:
+                    Keyword::Class
+                    | Keyword::Fun
+                    | Keyword::Var
+                    | Keyword::For
+                    | Keyword::If
+                    | Keyword::While
+                    | Keyword::Print
+                    | Keyword::Return,
+                ..
+            } => return,
+            _ => {
+                tokens_iter.next();
             }
-            _ => break,
         }
     }
+}
 
-    Some(left)
+fn check(tokens_iter: &mut Tokens<'_>, matcher: fn(&TokenType) -> bool) -> bool {
+    tokens_iter
+        .peek()
+        .is_none_or(|token| matcher(&token.token_type))
 }
 
-fn parse_unary(tokens_iter: &mut Peekable<std::slice::Iter<'_, Token>>) -> Option<Expr> {
-    let mut expr = None;
-    if let Some(token) = tokens_iter.peek() {
-        match &token.token_type {
-            TokenType::Minus | TokenType::Bang => {
-                let consumed_token = tokens_iter.next()?;
-                let right = parse_unary(tokens_iter)?;
-                expr = Some(Expr::Unary {
-                    right: Box::new(right),
-                    operator: consumed_token.clone(),
-                });
+/// Consumes and returns the next token only if it matches.
+fn match_token(tokens_iter: &mut Tokens<'_>, matcher: fn(&TokenType) -> bool) -> Option<Token> {
+    tokens_iter.next_if(|token| matcher(&token.token_type))
+}
+
+/// Consumes the next token if it matches, otherwise reports `message` against
+/// it and leaves it in place.
+fn consume(
+    tokens_iter: &mut Tokens<'_>,
+    matcher: fn(&TokenType) -> bool,
+    message: &str,
+) -> ParseResult<Token> {
+    if let Some(token) = match_token(tokens_iter, matcher) {
+        return Ok(token);
+    }
+    Err(ParseError::new(peek_token(tokens_iter), message))
+}
+
+/// Returns the next token without consuming it. The scanner always ends the
+/// stream with `EOF`, which the parser never consumes, so this only falls
+/// back to a synthetic one for an empty stream.
+fn peek_token<'a>(tokens_iter: &'a mut Tokens<'_>) -> &'a Token {
+    static END: Token = Token {
+        token_type: TokenType::EOF,
+        lexeme: String::new(),
+        line: 0,
+        span: Span {
+            start: 0,
+            end: 0,
+            column: 0,
+        },
+        doc: None,
+        leading_trivia: Vec::new(),
+    };
+    tokens_iter.peek().unwrap_or(&END)
+}
+
+/// Parses one declaration. On a syntax error, records it in `errors` and
+/// skips to the next statement boundary so the enclosing program or block
+/// can carry on.
+fn parse_declaration_or_recover(
+    tokens_iter: &mut Tokens<'_>,
+    errors: &mut Vec<ParseError>,
+) -> Option<Stmt> {
+    match parse_declaration(tokens_iter, errors) {
+        Ok(stmt) => Some(stmt),
+        Err(error) => {
+            errors.push(error);
+            synchronize(tokens_iter);
+            None
+        }
+    }
+}
+
+/// Errors inside nested blocks are recovered from locally and land in
+/// `errors`; only an error in the declaration itself is returned.
+fn parse_declaration(
+    tokens_iter: &mut Tokens<'_>,
+    errors: &mut Vec<ParseError>,
+) -> ParseResult<Stmt> {
+    // Doc comments ride on the declaration's first token.
+    let doc = peek_token(tokens_iter).doc.clone();
+    match tokens_iter.peek().map(|token| &token.token_type) {
+        Some(TokenType::Keyword {
+            kw: Keyword::Var, ..
+        }) => {
+            tokens_iter.next();
+            parse_var_declaration(tokens_iter, doc)
+        }
+        Some(TokenType::Keyword {
+            kw: Keyword::Fun, ..
+        }) => {
+            tokens_iter.next();
+            Ok(Stmt::Function(Rc::new(parse_function(
+                tokens_iter,
+                "function",
+                doc,
+                errors,
+            )?)))
+        }
+        Some(TokenType::Keyword {
+            kw: Keyword::Class, ..
+        }) => {
+            tokens_iter.next();
+            parse_class_declaration(tokens_iter, doc, errors)
+        }
+        _ => parse_statement(tokens_iter, errors),
+    }
+}
+
+fn parse_class_declaration(
+    tokens_iter: &mut Tokens<'_>,
+    doc: Option<String>,
+    errors: &mut Vec<ParseError>,
+) -> ParseResult<Stmt> {
+    let name = consume(
+        tokens_iter,
+        |t| matches!(t, TokenType::Identifier(_)),
+        "Expect class name.",
+    )?;
+    let superclass = match match_token(tokens_iter, |t| matches!(t, TokenType::Less)) {
+        Some(_) => {
+            let superclass_name = consume(
+                tokens_iter,
+                |t| matches!(t, TokenType::Identifier(_)),
+                "Expect superclass name.",
+            )?;
+            Some(Expr::Variable {
+                name: superclass_name.clone(),
+                depth: Depth::default(),
+            })
+        }
+        None => None,
+    };
+    consume(
+        tokens_iter,
+        |t| matches!(t, TokenType::LeftBrace),
+        "Expect '{' before class body.",
+    )?;
+    let mut methods = Vec::new();
+    while !check(tokens_iter, |t| {
+        matches!(t, TokenType::RightBrace | TokenType::EOF)
+    }) {
+        let method_doc = peek_token(tokens_iter).doc.clone();
+        match parse_function(tokens_iter, "method", method_doc, errors) {
+            Ok(method) => methods.push(Rc::new(method)),
+            Err(error) => {
+                errors.push(error);
+                synchronize(tokens_iter);
+                // Anything but another method or the closing brace means
+                // skipping ran past the body, e.g. because its `}` is
+                // missing: the error is already recorded, so leave the rest
+                // to the enclosing declarations.
+                if !check(tokens_iter, |t| {
+                    matches!(t, TokenType::Identifier(_) | TokenType::RightBrace)
+                }) {
+                    return Ok(Stmt::Class {
+                        name,
+                        superclass,
+                        methods,
+                        doc,
+                    });
+                }
             }
-            _ => {
-                expr = parse_primary(tokens_iter);
+        }
+    }
+    consume(
+        tokens_iter,
+        |t| matches!(t, TokenType::RightBrace),
+        "Expect '}' after class body.",
+    )?;
+    Ok(Stmt::Class {
+        name,
+        superclass,
+        methods,
+        doc,
+    })
+}
+
+/// Parses the name, parameters and body of a function whose introducing
+/// keyword has already been consumed. `kind` only shapes error messages.
+fn parse_function(
+    tokens_iter: &mut Tokens<'_>,
+    kind: &str,
+    doc: Option<String>,
+    errors: &mut Vec<ParseError>,
+) -> ParseResult<FunctionDecl> {
+    let name = consume(
+        tokens_iter,
+        |t| matches!(t, TokenType::Identifier(_)),
+        &format!("Expect {kind} name."),
+    )?;
+    consume(
+        tokens_iter,
+        |t| matches!(t, TokenType::LeftParen),
+        &format!("Expect '(' after {kind} name."),
+    )?;
+    let mut params = Vec::new();
+    if !check(tokens_iter, |t| matches!(t, TokenType::RightParen)) {
+        loop {
+            let param = consume(
+                tokens_iter,
+                |t| matches!(t, TokenType::Identifier(_)),
+                "Expect parameter name.",
+            )?;
+            if params.len() >= MAX_ARGUMENTS {
+                return Err(ParseError::new(
+                    &param,
+                    "Can't have more than 255 parameters.",
+                ));
+            }
+            params.push(param.clone());
+            if match_token(tokens_iter, |t| matches!(t, TokenType::Comma)).is_none() {
+                break;
             }
         }
     }
-    expr
+    consume(
+        tokens_iter,
+        |t| matches!(t, TokenType::RightParen),
+        "Expect ')' after parameters.",
+    )?;
+    consume(
+        tokens_iter,
+        |t| matches!(t, TokenType::LeftBrace),
+        &format!("Expect '{{' before {kind} body."),
+    )?;
+    let body = parse_block(tokens_iter, errors)?;
+    Ok(FunctionDecl {
+        name,
+        params,
+        body,
+        doc,
+    })
 }
 
-fn parse_primary(tokens_iter: &mut Peekable<std::slice::Iter<'_, Token>>) -> Option<Expr> {
-    if let Some(token) = tokens_iter.next() {
-        match &token.token_type {
+fn parse_var_declaration(tokens_iter: &mut Tokens<'_>, doc: Option<String>) -> ParseResult<Stmt> {
+    let name = consume(
+        tokens_iter,
+        |t| matches!(t, TokenType::Identifier(_)),
+        "Expect variable name.",
+    )?;
+    let initializer = match tokens_iter.peek().map(|token| &token.token_type) {
+        Some(TokenType::Equal) => {
+            tokens_iter.next();
+            Some(parse_expression(tokens_iter)?)
+        }
+        _ => None,
+    };
+    consume(
+        tokens_iter,
+        |t| matches!(t, TokenType::Semicolon),
+        "Expect ';' after variable declaration.",
+    )?;
+    Ok(Stmt::Var {
+        name,
+        initializer,
+        doc,
+    })
+}
+
+fn parse_statement(
+    tokens_iter: &mut Tokens<'_>,
+    errors: &mut Vec<ParseError>,
+) -> ParseResult<Stmt> {
+    let token_type = tokens_iter.peek().map(|token| &token.token_type);
+    match token_type {
+        Some(TokenType::Keyword {
+            kw: Keyword::Print, ..
+        }) => {
+            let keyword = tokens_iter.next().unwrap();
+            let value = parse_expression(tokens_iter)?;
+            consume(
+                tokens_iter,
+                |t| matches!(t, TokenType::Semicolon),
+                "Expect ';' after value.",
+            )?;
+            Ok(Stmt::Print { keyword, value })
+        }
+        Some(TokenType::LeftBrace) => {
+            tokens_iter.next();
+            Ok(Stmt::Block(parse_block(tokens_iter, errors)?))
+        }
+        Some(TokenType::Keyword {
+            kw: Keyword::If, ..
+        }) => {
+            tokens_iter.next();
+            parse_if_statement(tokens_iter, errors)
+        }
+        Some(TokenType::Keyword {
+            kw: Keyword::While, ..
+        }) => {
+            tokens_iter.next();
+            parse_while_statement(tokens_iter, errors)
+        }
+        Some(TokenType::Keyword {
+            kw: Keyword::For, ..
+        }) => {
+            tokens_iter.next();
+            parse_for_statement(tokens_iter, errors)
+        }
+        Some(TokenType::Keyword {
+            kw: Keyword::Return,
+            ..
+        }) => {
+            let keyword = tokens_iter.next().unwrap();
+            let value = match tokens_iter.peek().map(|token| &token.token_type) {
+                Some(TokenType::Semicolon) => None,
+                _ => Some(parse_expression(tokens_iter)?),
+            };
+            consume(
+                tokens_iter,
+                |t| matches!(t, TokenType::Semicolon),
+                "Expect ';' after return value.",
+            )?;
+            Ok(Stmt::Return { keyword, value })
+        }
+        _ => parse_expression_statement(tokens_iter),
+    }
+}
+
+fn parse_expression_statement(tokens_iter: &mut Tokens<'_>) -> ParseResult<Stmt> {
+    let expr = parse_expression(tokens_iter)?;
+    consume(
+        tokens_iter,
+        |t| matches!(t, TokenType::Semicolon),
+        "Expect ';' after expression.",
+    )?;
+    Ok(Stmt::Expression(expr))
+}
+
+fn parse_if_statement(
+    tokens_iter: &mut Tokens<'_>,
+    errors: &mut Vec<ParseError>,
+) -> ParseResult<Stmt> {
+    consume(
+        tokens_iter,
+        |t| matches!(t, TokenType::LeftParen),
+        "Expect '(' after 'if'.",
+    )?;
+    let condition = parse_expression(tokens_iter)?;
+    consume(
+        tokens_iter,
+        |t| matches!(t, TokenType::RightParen),
+        "Expect ')' after if condition.",
+    )?;
+    let then_branch = Box::new(parse_statement(tokens_iter, errors)?);
+    let else_branch = match match_token(tokens_iter, |t| {
+        matches!(
+            t,
             TokenType::Keyword {
-                kw: Keyword::True, ..
-            } => Some(Expr::Bool(true)),
+                kw: Keyword::Else,
+                ..
+            }
+        )
+    }) {
+        Some(_) => Some(Box::new(parse_statement(tokens_iter, errors)?)),
+        None => None,
+    };
+    Ok(Stmt::If {
+        condition,
+        then_branch,
+        else_branch,
+    })
+}
+
+fn parse_while_statement(
+    tokens_iter: &mut Tokens<'_>,
+    errors: &mut Vec<ParseError>,
+) -> ParseResult<Stmt> {
+    consume(
+        tokens_iter,
+        |t| matches!(t, TokenType::LeftParen),
+        "Expect '(' after 'while'.",
+    )?;
+    let condition = parse_expression(tokens_iter)?;
+    consume(
+        tokens_iter,
+        |t| matches!(t, TokenType::RightParen),
+        "Expect ')' after condition.",
+    )?;
+    let body = Box::new(parse_statement(tokens_iter, errors)?);
+    Ok(Stmt::While { condition, body })
+}
+
+/// Desugars `for (init; cond; incr) body` into
+/// `{ init; while (cond) { body; incr; } }`, so the evaluator only needs to
+/// know about `while`.
+fn parse_for_statement(
+    tokens_iter: &mut Tokens<'_>,
+    errors: &mut Vec<ParseError>,
+) -> ParseResult<Stmt> {
+    consume(
+        tokens_iter,
+        |t| matches!(t, TokenType::LeftParen),
+        "Expect '(' after 'for'.",
+    )?;
+    let initializer = match tokens_iter.peek().map(|token| &token.token_type) {
+        Some(TokenType::Semicolon) => {
+            tokens_iter.next();
+            None
+        }
+        Some(TokenType::Keyword {
+            kw: Keyword::Var, ..
+        }) => {
+            tokens_iter.next();
+            Some(parse_var_declaration(tokens_iter, None)?)
+        }
+        _ => Some(parse_expression_statement(tokens_iter)?),
+    };
+    let condition = match tokens_iter.peek().map(|token| &token.token_type) {
+        Some(TokenType::Semicolon) => Expr::Bool(true, peek_token(tokens_iter).clone()),
+        _ => parse_expression(tokens_iter)?,
+    };
+    consume(
+        tokens_iter,
+        |t| matches!(t, TokenType::Semicolon),
+        "Expect ';' after loop condition.",
+    )?;
+    let increment = match tokens_iter.peek().map(|token| &token.token_type) {
+        Some(TokenType::RightParen) => None,
+        _ => Some(parse_expression(tokens_iter)?),
+    };
+    consume(
+        tokens_iter,
+        |t| matches!(t, TokenType::RightParen),
+        "Expect ')' after for clauses.",
+    )?;
+
+    let mut body = parse_statement(tokens_iter, errors)?;
+    if let Some(increment) = increment {
+        body = Stmt::Block(vec![body, Stmt::Expression(increment)]);
+    }
+    body = Stmt::While {
+        condition,
+        body: Box::new(body),
+    };
+    if let Some(initializer) = initializer {
+        body = Stmt::Block(vec![initializer, body]);
+    }
+    Ok(body)
+}
+
+/// Parses the statements of a block whose `{` has already been consumed,
+/// recovering from errors in them one declaration at a time.
+fn parse_block(
+    tokens_iter: &mut Tokens<'_>,
+    errors: &mut Vec<ParseError>,
+) -> ParseResult<Vec<Stmt>> {
+    let mut statements = Vec::new();
+    while !check(tokens_iter, |t| {
+        matches!(t, TokenType::RightBrace | TokenType::EOF)
+    }) {
+        statements.extend(parse_declaration_or_recover(tokens_iter, errors));
+    }
+    consume(
+        tokens_iter,
+        |t| matches!(t, TokenType::RightBrace),
+        "Expect '}' after block.",
+    )?;
+    Ok(statements)
+}
+
+const MAX_ARGUMENTS: usize = 255;
+
+fn parse_expression(tokens_iter: &mut Tokens<'_>) -> ParseResult<Expr> {
+    parse_assignment(tokens_iter)
+}
+
+fn parse_assignment(tokens_iter: &mut Tokens<'_>) -> ParseResult<Expr> {
+    let expr = parse_or(tokens_iter)?;
+    let Some(equals) = match_token(tokens_iter, |t| matches!(t, TokenType::Equal)) else {
+        return Ok(expr);
+    };
+    let value = parse_assignment(tokens_iter)?;
+    match expr {
+        Expr::Variable { name, .. } => Ok(Expr::Assign {
+            name,
+            value: Box::new(value),
+            depth: Depth::default(),
+        }),
+        Expr::Get { object, name } => Ok(Expr::Set {
+            object,
+            name,
+            value: Box::new(value),
+        }),
+        _ => Err(ParseError::new(&equals, "Invalid assignment target.")),
+    }
+}
+
+fn parse_or(tokens_iter: &mut Tokens<'_>) -> ParseResult<Expr> {
+    parse_logical(tokens_iter, parse_and, |token_type| {
+        matches!(
+            token_type,
             TokenType::Keyword {
-                kw: Keyword::False, ..
-            } => Some(Expr::Bool(false)),
+                kw: Keyword::Or,
+                ..
+            }
+        )
+    })
+}
+
+fn parse_and(tokens_iter: &mut Tokens<'_>) -> ParseResult<Expr> {
+    parse_logical(tokens_iter, parse_equality, |token_type| {
+        matches!(
+            token_type,
             TokenType::Keyword {
-                kw: Keyword::Nil, ..
-            } => Some(Expr::Nil),
-            TokenType::Number(val) => Some(Expr::Number(string_to_f64(&val).unwrap())),
-            TokenType::String {
-                string,
-                finished: true,
-            } => Some(Expr::String(trim_string(&string))),
-            TokenType::LeftParen => {
-                let mut depth = 1;
-                let mut enclosed_tokens = Vec::new();
-                while let Some(next_token) = tokens_iter.next() {
-                    match &next_token.token_type {
-                        TokenType::LeftParen => {
-                            depth += 1;
-                            enclosed_tokens.push(next_token.clone());
-                        }
-                        TokenType::RightParen => {
-                            depth -= 1;
-                            if depth == 0 {
-                                break;
-                            }
-                            enclosed_tokens.push(next_token.clone());
-                        }
-                        _ => enclosed_tokens.push(next_token.clone()),
-                    }
+                kw: Keyword::And,
+                ..
+            }
+        )
+    })
+}
+
+/// Same shape as [`parse_left_associative`], but builds [`Expr::Logical`]
+/// nodes so the evaluator can short-circuit them.
+fn parse_logical(
+    tokens_iter: &mut Tokens<'_>,
+    operand: fn(&mut Tokens<'_>) -> ParseResult<Expr>,
+    is_operator: fn(&TokenType) -> bool,
+) -> ParseResult<Expr> {
+    let mut left = operand(tokens_iter)?;
+    while let Some(consumed_token) = match_token(tokens_iter, is_operator) {
+        let right = operand(tokens_iter)?;
+        left = Expr::Logical {
+            operator: consumed_token.clone(),
+            left: Box::new(left),
+            right: Box::new(right),
+        };
+    }
+
+    Ok(left)
+}
+
+fn parse_equality(tokens_iter: &mut Tokens<'_>) -> ParseResult<Expr> {
+    parse_left_associative(tokens_iter, parse_comparison, |token_type| {
+        matches!(
+            token_type,
+            TokenType::Operator {
+                op: Operator::EqualEqual | Operator::BangEqual,
+            }
+        )
+    })
+}
+
+fn parse_comparison(tokens_iter: &mut Tokens<'_>) -> ParseResult<Expr> {
+    parse_left_associative(tokens_iter, parse_term, |token_type| {
+        matches!(
+            token_type,
+            TokenType::Greater
+                | TokenType::Less
+                | TokenType::Operator {
+                    op: Operator::LessEqual | Operator::GreaterEqual,
                 }
+        )
+    })
+}
 
-                if depth != 0 || enclosed_tokens.is_empty() {
-                    writeln!(io::stderr(), "Error: Unmatched parentheses.").unwrap();
-                    process::exit(65);
+fn parse_term(tokens_iter: &mut Tokens<'_>) -> ParseResult<Expr> {
+    parse_left_associative(tokens_iter, parse_factor, |token_type| {
+        matches!(token_type, TokenType::Plus | TokenType::Minus)
+    })
+}
+
+fn parse_factor(tokens_iter: &mut Tokens<'_>) -> ParseResult<Expr> {
+    parse_left_associative(tokens_iter, parse_unary, |token_type| {
+        matches!(token_type, TokenType::Slash | TokenType::Star)
+    })
+}
+
+/// Parses `operand (operator operand)*` and folds the result to the left, so
+/// `a - b - c` becomes `((a - b) - c)`.
+fn parse_left_associative(
+    tokens_iter: &mut Tokens<'_>,
+    operand: fn(&mut Tokens<'_>) -> ParseResult<Expr>,
+    is_operator: fn(&TokenType) -> bool,
+) -> ParseResult<Expr> {
+    let mut left = operand(tokens_iter)?;
+    while let Some(token) = tokens_iter.peek() {
+        if !is_operator(&token.token_type) {
+            break;
+        }
+        let consumed_token = tokens_iter.next().unwrap();
+        let right = operand(tokens_iter)?;
+        left = Expr::Binary {
+            operator: consumed_token.clone(),
+            left: Box::new(left),
+            right: Box::new(right),
+        };
+    }
+
+    Ok(left)
+}
+
+fn parse_unary(tokens_iter: &mut Tokens<'_>) -> ParseResult<Expr> {
+    match match_token(tokens_iter, |t| {
+        matches!(t, TokenType::Minus | TokenType::Bang)
+    }) {
+        Some(consumed_token) => {
+            let right = parse_unary(tokens_iter)?;
+            Ok(Expr::Unary {
+                right: Box::new(right),
+                operator: consumed_token.clone(),
+            })
+        }
+        None => parse_call(tokens_iter),
+    }
+}
+
+fn parse_call(tokens_iter: &mut Tokens<'_>) -> ParseResult<Expr> {
+    let mut expr = parse_primary(tokens_iter)?;
+    while let Some(token) = match_token(tokens_iter, |t| {
+        matches!(t, TokenType::LeftParen | TokenType::Dot)
+    }) {
+        if matches!(token.token_type, TokenType::Dot) {
+            let name = consume(
+                tokens_iter,
+                |t| matches!(t, TokenType::Identifier(_)),
+                "Expect property name after '.'.",
+            )?;
+            expr = Expr::Get {
+                object: Box::new(expr),
+                name: name.clone(),
+            };
+            continue;
+        }
+        let mut arguments = Vec::new();
+        if !check(tokens_iter, |t| matches!(t, TokenType::RightParen)) {
+            loop {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    return Err(ParseError::new(
+                        peek_token(tokens_iter),
+                        "Can't have more than 255 arguments.",
+                    ));
                 }
-                let mut enclosed_tokens_iter = enclosed_tokens.iter().peekable();
-                if let Some(enclosed_epxr) = parse_expression(&mut enclosed_tokens_iter) {
-                    Some(Expr::Grouping(Box::new(enclosed_epxr)))
-                } else {
-                    writeln!(
-                        io::stderr(),
-                        "Error: Failed to parse expression inside parentheses."
-                    )
-                    .unwrap();
-                    process::exit(65);
+                arguments.push(parse_expression(tokens_iter)?);
+                if match_token(tokens_iter, |t| matches!(t, TokenType::Comma)).is_none() {
+                    break;
                 }
             }
-            TokenType::EOF => None,
-            t => {
-                writeln!(io::stderr(), "Error: lol: {t}").unwrap();
-                process::exit(65);
-            }
         }
-    } else {
-        None
+        let paren = consume(
+            tokens_iter,
+            |t| matches!(t, TokenType::RightParen),
+            "Expect ')' after arguments.",
+        )?
+        .clone();
+        expr = Expr::Call {
+            callee: Box::new(expr),
+            paren,
+            arguments,
+        };
+    }
+    Ok(expr)
+}
+
+fn parse_primary(tokens_iter: &mut Tokens<'_>) -> ParseResult<Expr> {
+    let token = peek_token(tokens_iter).clone();
+    let expr = match &token.token_type {
+        TokenType::Keyword {
+            kw: Keyword::True, ..
+        } => Expr::Bool(true, token.clone()),
+        TokenType::Keyword {
+            kw: Keyword::False, ..
+        } => Expr::Bool(false, token.clone()),
+        TokenType::Keyword {
+            kw: Keyword::Nil, ..
+        } => Expr::Nil(token.clone()),
+        TokenType::Number(val) => Expr::Number(
+            parse_number(val).expect("the scanner only emits well-formed numbers"),
+            token.clone(),
+        ),
+        TokenType::String(string) => Expr::String(trim_string(string), token.clone()),
+        TokenType::Identifier(_) => Expr::Variable {
+            name: token.clone(),
+            depth: Depth::default(),
+        },
+        TokenType::Keyword {
+            kw: Keyword::This, ..
+        } => Expr::This {
+            keyword: token.clone(),
+            depth: Depth::default(),
+        },
+        TokenType::Keyword {
+            kw: Keyword::Super, ..
+        } => {
+            tokens_iter.next();
+            consume(
+                tokens_iter,
+                |t| matches!(t, TokenType::Dot),
+                "Expect '.' after 'super'.",
+            )?;
+            let method = consume(
+                tokens_iter,
+                |t| matches!(t, TokenType::Identifier(_)),
+                "Expect superclass method name.",
+            )?;
+            return Ok(Expr::Super {
+                keyword: token.clone(),
+                method,
+                depth: Depth::default(),
+            });
+        }
+        TokenType::LeftParen => {
+            tokens_iter.next();
+            let expr = parse_expression(tokens_iter)?;
+            let right_paren = consume(
+                tokens_iter,
+                |t| matches!(t, TokenType::RightParen),
+                "Expect ')' after expression.",
+            )?;
+            return Ok(Expr::Grouping(
+                Box::new(expr),
+                token.span.to(right_paren.span),
+            ));
+        }
+        _ => return Err(ParseError::new(&token, "Expect expression.")),
+    };
+    tokens_iter.next();
+    Ok(expr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::tokenize::scanner;
+
+    fn errors(source: &str) -> Vec<String> {
+        let (tokens, _) = scanner(source);
+        match parse_program(&mut tokens.into_iter()) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors
+                .iter()
+                .map(|error| format!("{} at '{}'", error.message, error.token.lexeme))
+                .collect(),
+        }
+    }
+
+    /// The expressions in `source`, printed as the `parse` command does.
+    fn parsed(source: &str) -> Vec<String> {
+        let (tokens, _) = scanner(source);
+        match parse_tokens(&mut tokens.into_iter()) {
+            Ok(exprs) => exprs.iter().map(ToString::to_string).collect(),
+            Err(errors) => panic!("{source}: {}", errors[0].message),
+        }
+    }
+
+    #[test]
+    fn binary_operators_bind_by_precedence() {
+        assert_eq!(parsed("1 + 2 * 3"), ["(+ 1.0 (* 2.0 3.0))"]);
+        assert_eq!(parsed("1 * 2 + 3"), ["(+ (* 1.0 2.0) 3.0)"]);
+        assert_eq!(parsed("1 < 2 == true"), ["(== (< 1.0 2.0) true)"]);
+        assert_eq!(parsed("-1 * !true"), ["(* (- 1.0) (! true))"]);
+        assert_eq!(parsed("(1 + 2) * 3"), ["(* (group (+ 1.0 2.0)) 3.0)"]);
+        assert_eq!(parsed("a or b and c == d"), ["(or a (and b (== c d)))"]);
+    }
+
+    #[test]
+    fn binary_operators_associate_left() {
+        assert_eq!(parsed("a - b - c"), ["(- (- a b) c)"]);
+        assert_eq!(parsed("8 / 4 / 2"), ["(/ (/ 8.0 4.0) 2.0)"]);
+        assert_eq!(parsed("1 < 2 < 3"), ["(< (< 1.0 2.0) 3.0)"]);
+        assert_eq!(parsed("a or b or c"), ["(or (or a b) c)"]);
+        assert_eq!(parsed("--1"), ["(- (- 1.0))"]);
+    }
+
+    #[test]
+    fn assignment_associates_right() {
+        assert_eq!(parsed("a = b = 1"), ["(= a (= b 1.0))"]);
+        assert_eq!(parsed("o.x = o.y = 2"), ["(= (. o x) (= (. o y) 2.0))"]);
+    }
+
+    #[test]
+    fn recovers_inside_blocks() {
+        assert_eq!(
+            errors("{ print ; print 2; }"),
+            ["Expect expression. at ';'"]
+        );
+        assert_eq!(
+            errors("fun f() { var x = 1 print x; }"),
+            ["Expect ';' after variable declaration. at 'print'"]
+        );
+        assert_eq!(
+            errors("while (true) { { print ; } print 1 +; } print -;"),
+            [
+                "Expect expression. at ';'",
+                "Expect expression. at ';'",
+                "Expect expression. at ';'",
+            ]
+        );
+    }
+
+    #[test]
+    fn recovers_inside_class_bodies() {
+        assert_eq!(
+            errors("class A { m() { print ; } n( { } } print 1 +;"),
+            [
+                "Expect expression. at ';'",
+                "Expect parameter name. at '{'",
+                "Expect expression. at ';'",
+            ]
+        );
+        assert_eq!(
+            errors("class A { m( } var x = 1; print x x;"),
+            [
+                "Expect parameter name. at '}'",
+                "Expect ';' after value. at 'x'",
+            ]
+        );
+    }
+
+    #[test]
+    fn doc_comments_reach_declarations() {
+        let source = "\
+/// Counts things.
+///   Indented.
+var count = 0;
+//// A ruler, not documentation.
+fun plain() {}
+/// A shape.
+class Shape {
+  /// The area.
+  area() { return 0; }
+  perimeter() { return 0; }
+}
+/* outer /* nested */ still outer */
+/// Documented.
+fun documented() {}
+";
+        let (tokens, errors) = scanner(source);
+        assert!(errors.is_empty());
+        let stmts = match parse_program(&mut tokens.into_iter()) {
+            Ok(stmts) => stmts,
+            Err(errors) => panic!("{}", errors[0].message),
+        };
+        let docs: Vec<Option<&str>> = stmts
+            .iter()
+            .map(|stmt| match stmt {
+                Stmt::Var { doc, .. } | Stmt::Class { doc, .. } => doc.as_deref(),
+                Stmt::Function(function) => function.doc.as_deref(),
+                _ => panic!("unexpected statement"),
+            })
+            .collect();
+        assert_eq!(
+            docs,
+            [
+                Some("Counts things.\n  Indented."),
+                None,
+                Some("A shape."),
+                Some("Documented."),
+            ]
+        );
+        let Stmt::Class { methods, .. } = &stmts[2] else {
+            panic!("expected a class");
+        };
+        let method_docs: Vec<Option<&str>> =
+            methods.iter().map(|method| method.doc.as_deref()).collect();
+        assert_eq!(method_docs, [Some("The area."), None]);
     }
 }
@@ -0,0 +1,106 @@
+use crate::diagnostics::ToDiagnostic;
+use crate::errors::ScanError;
+use crate::tokens::{Span, Token, TokenType};
+use crate::utils::{format_number, parse_number, trim_string, NumberStyle};
+
+/// Quotes `text` as a JSON string.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn span_json(span: &Span) -> String {
+    format!(
+        r#"{{"start":{},"end":{},"column":{}}}"#,
+        span.start, span.end, span.column
+    )
+}
+
+/// The token's value as a JSON literal: the decoded text of a string, the
+/// value of a number and `null` for everything else.
+fn literal_json(token: &Token) -> String {
+    match &token.token_type {
+        TokenType::String(string) => json_string(&trim_string(string)),
+        // JSON has no infinity, which a literal like `1e999` overflows to.
+        TokenType::Number(number) => parse_number(number)
+            .filter(|value| value.is_finite())
+            .map_or_else(
+                || "null".to_string(),
+                |value| format_number(value, NumberStyle::Literal),
+            ),
+        _ => "null".to_string(),
+    }
+}
+
+fn token_json(token: &Token) -> String {
+    format!(
+        r#"{{"type":"token","kind":{},"lexeme":{},"literal":{},"line":{},"span":{}}}"#,
+        json_string(&token.token_type.to_string()),
+        json_string(&token.lexeme),
+        literal_json(token),
+        token.line,
+        span_json(&token.span)
+    )
+}
+
+fn error_json(error: &ScanError) -> String {
+    let diagnostic = error.to_diagnostic();
+    format!(
+        r#"{{"type":"error","code":{},"message":{},"line":{},"span":{}}}"#,
+        json_string(diagnostic.code),
+        json_string(&diagnostic.message),
+        error.line(),
+        span_json(&diagnostic.span)
+    )
+}
+
+fn result_json(result: &Result<Token, ScanError>) -> String {
+    match result {
+        Ok(token) => token_json(token),
+        Err(error) => error_json(error),
+    }
+}
+
+/// Prints a single JSON document with every token and every scan error.
+/// Returns whether there were any errors.
+pub fn print_tokens_json(results: impl Iterator<Item = Result<Token, ScanError>>) -> bool {
+    let (tokens, errors): (Vec<_>, Vec<_>) = results.partition(Result::is_ok);
+    let render = |results: &[Result<Token, ScanError>]| {
+        results
+            .iter()
+            .map(result_json)
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    println!(
+        r#"{{"tokens":[{}],"errors":[{}]}}"#,
+        render(&tokens),
+        render(&errors)
+    );
+    !errors.is_empty()
+}
+
+/// Prints one JSON object per line as the scanner produces them, tokens and
+/// errors interleaved in source order. Returns whether there were any
+/// errors.
+pub fn print_tokens_ndjson(results: impl Iterator<Item = Result<Token, ScanError>>) -> bool {
+    let mut had_errors = false;
+    for result in results {
+        had_errors |= result.is_err();
+        println!("{}", result_json(&result));
+    }
+    had_errors
+}
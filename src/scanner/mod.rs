@@ -1 +1,3 @@
+pub mod json;
 pub mod tokenize;
+mod xid;
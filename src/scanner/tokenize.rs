@@ -1,28 +1,143 @@
-use crate::tokens::{Operator, Token, TokenType, KEYWORDS};
-use crate::utils::{format_number_as_string, trim_string};
-use std::io::{self, Write};
-use std::process;
+use std::collections::VecDeque;
+use std::iter::Peekable;
+use std::str::CharIndices;
 
+use super::xid;
+use crate::errors::ScanError;
+use crate::tokens::{Operator, Span, Token, TokenType, Trivia, TriviaKind, KEYWORDS};
+use crate::utils::{format_number, parse_number, trim_string, unescape, NumberStyle};
+
+/// Consumes the next character and returns `operator_type` if it is
+/// `match_char`, otherwise leaves it alone and returns `simple_type`.
 fn gen_operator(
-    char_: Option<char>,
+    chars: &mut Peekable<CharIndices<'_>>,
     operator_type: TokenType,
     simple_type: TokenType,
     match_char: char,
 ) -> TokenType {
-    if let Some(i) = char_ {
-        if i == match_char {
-            return operator_type;
+    if chars.next_if(|&(_, c)| c == match_char).is_some() {
+        return operator_type;
+    }
+    simple_type
+}
+
+/// Returns the character after the next one without consuming anything.
+fn peek_second(chars: &Peekable<CharIndices<'_>>) -> Option<char> {
+    let mut lookahead = chars.clone();
+    lookahead.next();
+    lookahead.next().map(|(_, c)| c)
+}
+
+/// Whether `c` can start an identifier: `_` or a character with Unicode's
+/// `XID_Start` property, so letters of any script but no digits.
+fn is_identifier_start(c: char) -> bool {
+    c == '_' || xid::is_xid_start(c)
+}
+
+/// Whether `c` can continue an identifier: a character with Unicode's
+/// `XID_Continue` property, which adds digits, combining marks and connector
+/// punctuation such as `_` to `XID_Start`.
+fn is_identifier_continue(c: char) -> bool {
+    xid::is_xid_continue(c)
+}
+
+pub fn print_tokens(tokens: &[Token]) {
+    for token in tokens.iter() {
+        match &token.token_type {
+            TokenType::String(string) => println!(
+                "{} {} {}",
+                token.token_type,
+                token.lexeme,
+                trim_string(string)
+            ),
+            TokenType::Number(_) => {
+                println!(
+                    "{} {} {}",
+                    token.token_type,
+                    token.lexeme,
+                    format_number(
+                        parse_number(&token.lexeme)
+                            .expect("the scanner only emits well-formed numbers"),
+                        NumberStyle::Literal
+                    )
+                )
+            }
+            _ => println!("{} {} null", token.token_type, token.lexeme),
         }
     }
-    return simple_type;
 }
 
-fn tokenize_line(line: &str, line_number: usize) -> Vec<Token> {
-    let mut tokens = Vec::<Token>::new();
-    let mut chars = line.chars().peekable();
+/// Classifies text the scanner skipped over between two tokens.
+fn trivia_kind(text: &str) -> TriviaKind {
+    if text.starts_with("///") && !text.starts_with("////") {
+        TriviaKind::DocComment
+    } else if text.starts_with("//") {
+        TriviaKind::LineComment
+    } else if text.starts_with("/*") {
+        TriviaKind::BlockComment
+    } else if text.chars().all(|c| matches!(c, ' ' | '\t' | '\r' | '\n')) {
+        TriviaKind::Whitespace
+    } else {
+        TriviaKind::Skipped
+    }
+}
 
-    while let Some(char) = chars.next() {
-        // let mut token_type = None;
+/// Splits a source string into tokens lazily, one per call to `next`,
+/// counting lines as newlines are consumed so tokens spanning several lines
+/// (multi-line strings) report the line they start on. Lexical errors don't
+/// stop the scan: the bad input is skipped and its errors are yielded in
+/// place of a token. The stream always ends with a single `EOF` token.
+pub struct Scanner<'a> {
+    source: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    line: usize,
+    /// Byte offset where the current line begins, for computing columns.
+    line_start: usize,
+    /// The last offset a column was computed for, and that column, so each
+    /// column is counted on from the previous token rather than from the
+    /// start of the line.
+    last_column: (usize, usize),
+    /// `///` lines seen since the last token, attached to the next one.
+    doc_lines: Vec<&'a str>,
+    /// Only collected in lossless mode.
+    trivia: Vec<Trivia>,
+    lossless: bool,
+    /// Errors found while scanning the last piece of input, not yet yielded.
+    errors: VecDeque<ScanError>,
+    finished: bool,
+}
+
+impl<'a> Scanner<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            chars: source.char_indices().peekable(),
+            line: 1,
+            line_start: 0,
+            last_column: (0, 1),
+            doc_lines: Vec::new(),
+            trivia: Vec::new(),
+            lossless: false,
+            errors: VecDeque::new(),
+            finished: false,
+        }
+    }
+
+    /// Keeps every whitespace run, comment and rejected piece of input as
+    /// trivia on the token that follows it (the `EOF` token takes whatever
+    /// ends the file), so concatenating each token's trivia and lexeme
+    /// reproduces the source exactly.
+    pub fn lossless(mut self) -> Self {
+        self.lossless = true;
+        self
+    }
+
+    /// Scans the lexeme starting with `char` at byte `start`, returning the
+    /// token it forms, or `None` for whitespace, comments and bad input.
+    fn scan_token(&mut self, start: usize, char: char) -> Option<Token> {
+        let source = self.source;
+        let start_line = self.line;
+        let column = self.column_at(start);
         let token_type = match char {
             '(' => Some(TokenType::LeftParen),
             ')' => Some(TokenType::RightParen),
@@ -35,7 +150,7 @@ fn tokenize_line(line: &str, line_number: usize) -> Vec<Token> {
             ';' => Some(TokenType::Semicolon),
             '-' => Some(TokenType::Minus),
             '=' => Some(gen_operator(
-                chars.peek().copied(),
+                &mut self.chars,
                 TokenType::Operator {
                     op: Operator::EqualEqual,
                 },
@@ -43,7 +158,7 @@ fn tokenize_line(line: &str, line_number: usize) -> Vec<Token> {
                 '=',
             )),
             '!' => Some(gen_operator(
-                chars.peek().copied(),
+                &mut self.chars,
                 TokenType::Operator {
                     op: Operator::BangEqual,
                 },
@@ -51,7 +166,7 @@ fn tokenize_line(line: &str, line_number: usize) -> Vec<Token> {
                 '=',
             )),
             '<' => Some(gen_operator(
-                chars.peek().copied(),
+                &mut self.chars,
                 TokenType::Operator {
                     op: Operator::LessEqual,
                 },
@@ -59,66 +174,182 @@ fn tokenize_line(line: &str, line_number: usize) -> Vec<Token> {
                 '=',
             )),
             '>' => Some(gen_operator(
-                chars.peek().copied(),
+                &mut self.chars,
                 TokenType::Operator {
                     op: Operator::GreaterEqual,
                 },
                 TokenType::Greater,
                 '=',
             )),
-            '/' => Some(gen_operator(
-                chars.peek().copied(),
-                TokenType::Comment,
-                TokenType::Slash,
-                '/',
-            )),
-            ' ' | '\n' | '\t' => None,
+            '/' if self.chars.next_if(|&(_, c)| c == '/').is_some() => {
+                // The newline is left for the main loop so it bumps `line`.
+                while self.chars.next_if(|&(_, c)| c != '\n').is_some() {}
+                let end = self.chars.peek().map_or(source.len(), |&(i, _)| i);
+                let comment = &source[start..end];
+                // `////...` rulers are plain comments, as in Rust.
+                if let Some(doc) = comment
+                    .strip_prefix("///")
+                    .filter(|doc| !doc.starts_with('/'))
+                {
+                    self.doc_lines
+                        .push(doc.strip_prefix(' ').unwrap_or(doc).trim_end());
+                }
+                None
+            }
+            '/' if self.chars.next_if(|&(_, c)| c == '*').is_some() => {
+                // Block comments nest, so `/* a /* b */ c */` is one comment.
+                let mut depth = 1;
+                while depth > 0 {
+                    match self.chars.next() {
+                        Some((_, '/')) if self.chars.next_if(|&(_, c)| c == '*').is_some() => {
+                            depth += 1
+                        }
+                        Some((_, '*')) if self.chars.next_if(|&(_, c)| c == '/').is_some() => {
+                            depth -= 1
+                        }
+                        Some((offset, '\n')) => {
+                            self.line += 1;
+                            self.line_start = offset + 1;
+                        }
+                        Some(_) => {}
+                        None => break,
+                    }
+                }
+                if depth > 0 {
+                    self.errors.push_back(ScanError::UnterminatedComment {
+                        line: start_line,
+                        span: Span {
+                            start,
+                            end: start + 2,
+                            column,
+                        },
+                    });
+                }
+                None
+            }
+            '/' => Some(TokenType::Slash),
+            '\n' => {
+                self.line += 1;
+                self.line_start = start + 1;
+                None
+            }
+            // `\r` comes from CRLF line endings; the `\n` does the counting.
+            ' ' | '\t' | '\r' => None,
+            // A byte order mark is only allowed as the very first character.
+            '\u{feff}' if start == 0 => {
+                self.line_start = char.len_utf8();
+                None
+            }
             '"' => {
                 let mut finished = false;
-                let mut string_ = r#"""#.to_string();
-                while let Some(new_char) = chars.next() {
-                    string_.push(new_char);
-                    if new_char == '"' {
+                let mut escaping = false;
+                for (offset, new_char) in self.chars.by_ref() {
+                    if new_char == '\n' {
+                        self.line += 1;
+                        self.line_start = offset + 1;
+                    }
+                    if new_char == '"' && !escaping {
                         finished = true;
                         break;
                     }
+                    escaping = new_char == '\\' && !escaping;
                 }
-                Some(TokenType::String {
-                    string: string_,
-                    finished,
-                })
-            }
-            char if char.is_digit(10) => {
-                let mut has_decimal = false;
-                let mut num_as_string = String::from(char);
-
-                while let Some(&next_char) = chars.peek() {
-                    match next_char {
-                        '0'..='9' => {
-                            num_as_string.push(next_char);
-                            chars.next();
-                        }
-                        '.' if !has_decimal => {
-                            num_as_string.push(next_char);
-                            has_decimal = true;
-                            chars.next();
+                if finished {
+                    let end = self.chars.peek().map_or(source.len(), |&(i, _)| i);
+                    match unescape(&source[start + 1..end - 1]) {
+                        Ok(_) => Some(TokenType::String(source[start..end].to_string())),
+                        Err(invalid) => {
+                            for escape in invalid {
+                                let escape_start = start + 1 + escape.start;
+                                let escape_end = start + 1 + escape.end;
+                                let escape_line_start =
+                                    source[..escape_start].rfind('\n').map_or(0, |i| i + 1);
+                                self.errors.push_back(ScanError::InvalidEscape {
+                                    sequence: source[escape_start..escape_end].to_string(),
+                                    line: start_line
+                                        + source[start..escape_start].matches('\n').count(),
+                                    span: Span {
+                                        start: escape_start,
+                                        end: escape_end,
+                                        column: source[escape_line_start..escape_start]
+                                            .chars()
+                                            .count()
+                                            + 1,
+                                    },
+                                });
+                            }
+                            None
                         }
-                        _ => break,
                     }
+                } else {
+                    self.errors.push_back(ScanError::UnterminatedString {
+                        line: start_line,
+                        span: Span {
+                            start,
+                            end: source.len(),
+                            column,
+                        },
+                    });
+                    None
                 }
-                Some(TokenType::Number(num_as_string))
             }
-            char if char.is_alphanumeric() || char == '_' => {
-                let mut cont = String::from(char);
-                while let Some(&n) = chars.peek() {
-                    match n {
-                        n if n.is_alphanumeric() || n == '_' => {
-                            cont.push(n);
-                            chars.next();
+            char if char.is_ascii_digit() => {
+                let is_digit_or_underscore =
+                    |&(_, c): &(usize, char)| c.is_ascii_digit() || c == '_';
+                if char == '0' && self.chars.next_if(|&(_, c)| "xXbB".contains(c)).is_some() {
+                    // Everything word-like after `0x`/`0b` belongs to the
+                    // literal, so `0b102` is one malformed number.
+                    while self
+                        .chars
+                        .next_if(|&(_, c)| c.is_ascii_alphanumeric() || c == '_')
+                        .is_some()
+                    {}
+                } else {
+                    while self.chars.next_if(is_digit_or_underscore).is_some() {}
+                    // A trailing `.` is only part of the number when a digit
+                    // follows, so `12.` scans as `12` then `.`.
+                    if self.chars.peek().is_some_and(|&(_, c)| c == '.')
+                        && peek_second(&self.chars).is_some_and(|c| c.is_ascii_digit())
+                    {
+                        self.chars.next();
+                        while self.chars.next_if(is_digit_or_underscore).is_some() {}
+                    }
+                    // Likewise `e` only starts an exponent when digits follow.
+                    if self
+                        .chars
+                        .peek()
+                        .is_some_and(|&(_, c)| c == 'e' || c == 'E')
+                    {
+                        let mut lookahead = self.chars.clone();
+                        lookahead.next();
+                        lookahead.next_if(|&(_, c)| c == '+' || c == '-');
+                        if lookahead.peek().is_some_and(|(_, c)| c.is_ascii_digit()) {
+                            self.chars = lookahead;
+                            while self.chars.next_if(is_digit_or_underscore).is_some() {}
                         }
-                        _ => break,
                     }
                 }
+                let end = self.chars.peek().map_or(source.len(), |&(i, _)| i);
+                let literal = &source[start..end];
+                if parse_number(literal).is_some() {
+                    Some(TokenType::Number(literal.to_string()))
+                } else {
+                    self.errors.push_back(ScanError::MalformedNumber {
+                        literal: literal.to_string(),
+                        line: self.line,
+                        span: Span { start, end, column },
+                    });
+                    None
+                }
+            }
+            char if is_identifier_start(char) => {
+                while self
+                    .chars
+                    .next_if(|&(_, c)| is_identifier_continue(c))
+                    .is_some()
+                {}
+                let end = self.chars.peek().map_or(source.len(), |&(i, _)| i);
+                let cont = source[start..end].to_string();
                 if let Some(kw) = KEYWORDS.get(cont.as_str()) {
                     Some(TokenType::Keyword {
                         kw: kw.clone(),
@@ -128,115 +359,302 @@ fn tokenize_line(line: &str, line_number: usize) -> Vec<Token> {
                     Some(TokenType::Identifier(cont))
                 }
             }
-            _ => Some(TokenType::Unknown(char.into())),
+            char if !char.is_ascii() => {
+                self.errors.push_back(ScanError::StrayUnicode {
+                    character: char,
+                    line: self.line,
+                    span: Span {
+                        start,
+                        end: start + char.len_utf8(),
+                        column,
+                    },
+                });
+                None
+            }
+            _ => {
+                self.errors.push_back(ScanError::UnexpectedCharacter {
+                    character: char,
+                    line: self.line,
+                    span: Span {
+                        start,
+                        end: start + char.len_utf8(),
+                        column,
+                    },
+                });
+                None
+            }
         };
 
-        if let Some(token_type_u) = token_type {
-            if matches!(token_type_u, TokenType::Comment) {
-                return tokens;
-            }
-            tokens.push(Token {
-                lexeme: match &token_type_u {
-                    TokenType::Unknown(_) => String::new(),
-                    TokenType::String {
-                        string,
-                        finished: _,
-                    } => string.clone(),
-                    TokenType::Number(num) => num.clone(),
-                    TokenType::Identifier(st) => st.clone(),
-                    TokenType::Keyword { kw: _, val } => val.clone(),
-                    TokenType::Operator { op: _ } => {
-                        let mut s = char.to_string();
-                        if let Some(next_char) = chars.next() {
-                            s.push(next_char)
-                        }
-                        s
-                    }
-                    _ => char.into(),
-                },
-                token_type: token_type_u.clone(),
-                line: line_number,
+        let end = self.chars.peek().map_or(source.len(), |&(i, _)| i);
+        if let Some(token_type) = token_type {
+            return Some(Token {
+                lexeme: source[start..end].to_string(),
+                token_type,
+                line: start_line,
+                span: Span { start, end, column },
+                doc: (!self.doc_lines.is_empty())
+                    .then(|| std::mem::take(&mut self.doc_lines).join("\n")),
+                leading_trivia: std::mem::take(&mut self.trivia),
             });
-
-            match token_type_u {
-                TokenType::Number(num) if num.ends_with('.') => tokens.push(Token {
-                    lexeme: '.'.into(),
-                    token_type: TokenType::Dot,
-                    line: line_number,
+        } else if self.lossless {
+            let text = &source[start..end];
+            let kind = trivia_kind(text);
+            match self.trivia.last_mut() {
+                // Adjacent whitespace (say a space then a newline) is one run.
+                Some(last) if kind == TriviaKind::Whitespace && last.kind == kind => {
+                    last.text.push_str(text);
+                    last.span.end = end;
+                }
+                _ => self.trivia.push(Trivia {
+                    kind,
+                    text: text.to_string(),
+                    span: Span { start, end, column },
                 }),
-                _ => (),
             }
         }
+        None
+    }
+
+    /// Returns the 1-based column, in characters, of byte `offset` on the
+    /// current line. Offsets must not decrease between calls.
+    fn column_at(&mut self, offset: usize) -> usize {
+        let (last_offset, last_column) = self.last_column;
+        let (from, column) = if last_offset >= self.line_start {
+            (last_offset, last_column)
+        } else {
+            (self.line_start, 1)
+        };
+        let column = column + self.source[from..offset].chars().count();
+        self.last_column = (offset, column);
+        column
+    }
+
+    fn eof(&mut self) -> Token {
+        let source = self.source;
+        Token {
+            lexeme: String::new(),
+            token_type: TokenType::EOF,
+            line: self.line,
+            span: Span {
+                start: source.len(),
+                end: source.len(),
+                column: self.column_at(source.len()),
+            },
+            doc: None,
+            leading_trivia: std::mem::take(&mut self.trivia),
+        }
     }
-    tokens
 }
 
-pub fn print_tokens(tokens: &Vec<Token>) {
-    let mut has_errored = false;
-    for token in tokens.iter() {
-        match &token.token_type {
-            TokenType::Unknown(unk) => {
-                has_errored = true;
-                writeln!(
-                    io::stderr(),
-                    "[line {}] Error: Unexpected character: {}",
-                    token.line,
-                    unk.as_str()
-                )
-                .unwrap()
+impl Iterator for Scanner<'_> {
+    type Item = Result<Token, ScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(error) = self.errors.pop_front() {
+                return Some(Err(error));
             }
-            TokenType::String {
-                string: _,
-                finished: false,
-            } => {
-                has_errored = true;
-                writeln!(
-                    io::stderr(),
-                    "[line {}] Error: Unterminated string.",
-                    token.line,
-                )
-                .unwrap()
+            if self.finished {
+                return None;
             }
-            TokenType::String {
-                string,
-                finished: true,
-            } => println!(
-                "{} {} {}",
-                token.token_type,
-                token.lexeme,
-                trim_string(&string)
-            ),
-            TokenType::Number(_) => {
-                println!(
-                    "{} {} {}",
-                    token.token_type,
-                    token.lexeme.trim_end_matches('.'),
-                    format_number_as_string(&token.lexeme)
-                )
+            let Some((start, char)) = self.chars.next() else {
+                self.finished = true;
+                return Some(Ok(self.eof()));
+            };
+            if let Some(token) = self.scan_token(start, char) {
+                return Some(Ok(token));
             }
-            _ => println!("{} {} null", token.token_type, token.lexeme),
         }
     }
+}
 
-    if has_errored {
-        process::exit(65);
+/// Scans all of `source` up front, separating the tokens from the errors.
+pub fn scanner(source: &str) -> (Vec<Token>, Vec<ScanError>) {
+    collect(Scanner::new(source))
+}
+
+/// Like [`scanner`], but in [`Scanner::lossless`] mode.
+pub fn scanner_lossless(source: &str) -> (Vec<Token>, Vec<ScanError>) {
+    collect(Scanner::new(source).lossless())
+}
+
+fn collect(scanner: Scanner) -> (Vec<Token>, Vec<ScanError>) {
+    let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+    for result in scanner {
+        match result {
+            Ok(token) => tokens.push(token),
+            Err(error) => errors.push(error),
+        }
     }
+    (tokens, errors)
 }
 
-pub fn scanner(source: String) -> Vec<Token> {
-    let lines = source.lines();
-    let mut tokens = Vec::<Token>::new();
-    let mut lines_count = 0;
-
-    for (i, line) in lines.enumerate() {
-        let mut line_tokens = tokenize_line(line, i + 1);
-        tokens.append(&mut line_tokens);
-        lines_count += 1;
-    }
-    tokens.push(Token {
-        lexeme: String::new(),
-        token_type: TokenType::EOF,
-        line: lines_count + 1,
-    });
-    tokens
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Each token's lexeme with the line it starts on.
+    fn lines(source: &str) -> Vec<(String, usize)> {
+        let (tokens, errors) = scanner(source);
+        assert!(errors.is_empty(), "{source:?}");
+        tokens
+            .into_iter()
+            .map(|token| (token.lexeme, token.line))
+            .collect()
+    }
+
+    #[test]
+    fn multi_line_strings_keep_their_starting_line() {
+        assert_eq!(
+            lines("var s = \"one\ntwo\nthree\";\nprint s;"),
+            [
+                ("var".to_string(), 1),
+                ("s".to_string(), 1),
+                ("=".to_string(), 1),
+                ("\"one\ntwo\nthree\"".to_string(), 1),
+                (";".to_string(), 3),
+                ("print".to_string(), 4),
+                ("s".to_string(), 4),
+                (";".to_string(), 4),
+                (String::new(), 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_count_their_lines() {
+        assert_eq!(
+            lines("a /* one\n /* two\n */ three\n */ b // c\nd"),
+            [
+                ("a".to_string(), 1),
+                ("b".to_string(), 4),
+                ("d".to_string(), 5),
+                (String::new(), 5),
+            ]
+        );
+    }
+
+    /// Each token's lexeme with its line, byte range and column.
+    fn spans(source: &str) -> Vec<(String, usize, usize, usize, usize)> {
+        let (tokens, errors) = scanner(source);
+        assert!(errors.is_empty(), "{source:?}");
+        tokens
+            .into_iter()
+            .map(|token| {
+                let Span { start, end, column } = token.span;
+                (token.lexeme, token.line, start, end, column)
+            })
+            .collect()
+    }
+
+    fn token(
+        lexeme: &str,
+        line: usize,
+        start: usize,
+        end: usize,
+        column: usize,
+    ) -> (String, usize, usize, usize, usize) {
+        (lexeme.to_string(), line, start, end, column)
+    }
+
+    #[test]
+    fn columns_restart_after_multi_line_tokens() {
+        assert_eq!(
+            spans("\"ab\ncd\" x\n/* é\n */ y"),
+            [
+                token("\"ab\ncd\"", 1, 0, 7, 1),
+                token("x", 2, 8, 9, 5),
+                token("y", 4, 20, 21, 5),
+                token("", 4, 21, 21, 6),
+            ]
+        );
+    }
+
+    #[test]
+    fn columns_count_characters_not_bytes() {
+        // A tab is one column; `é` is two bytes but one column.
+        assert_eq!(
+            spans("\té = \"ü\";"),
+            [
+                token("é", 1, 1, 3, 2),
+                token("=", 1, 4, 5, 4),
+                token("\"ü\"", 1, 6, 10, 6),
+                token(";", 1, 10, 11, 9),
+                token("", 1, 11, 11, 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_leading_bom_takes_no_column() {
+        assert_eq!(
+            spans("\u{feff}var x;\r\ny"),
+            [
+                token("var", 1, 3, 6, 1),
+                token("x", 1, 7, 8, 5),
+                token(";", 1, 8, 9, 6),
+                token("y", 2, 11, 12, 1),
+                token("", 2, 12, 12, 2),
+            ]
+        );
+    }
+
+    /// The lexemes of the tokens `source` scans to, end of file excluded.
+    fn lexemes(source: &str) -> Vec<String> {
+        let (tokens, errors) = scanner(source);
+        assert!(errors.is_empty(), "{source:?}");
+        tokens
+            .into_iter()
+            .filter(|token| !matches!(token.token_type, TokenType::EOF))
+            .map(|token| token.lexeme)
+            .collect()
+    }
+
+    #[test]
+    fn identifiers_take_combining_marks() {
+        // `्` is a nonspacing mark (Mn) and `े` a spacing one (Mc).
+        assert_eq!(lexemes("नमस्ते = 1;"), ["नमस्ते", "=", "1", ";"]);
+        // A decomposed `café`: `e` then U+0301 COMBINING ACUTE ACCENT.
+        assert_eq!(lexemes("cafe\u{301}.x"), ["cafe\u{301}", ".", "x"]);
+    }
+
+    #[test]
+    fn identifiers_take_connector_punctuation() {
+        assert_eq!(
+            lexemes("a\u{203f}b _c\u{2040}"),
+            ["a\u{203f}b", "_c\u{2040}"]
+        );
+    }
+
+    #[test]
+    fn identifiers_start_with_xid_start_only() {
+        // A combining mark continues an identifier but can't start one.
+        let (_, errors) = scanner("\u{301}a");
+        assert!(matches!(
+            errors[..],
+            [ScanError::StrayUnicode {
+                character: '\u{301}',
+                ..
+            }]
+        ));
+        // `²` is numeric but not `XID_Continue`.
+        let (tokens, errors) = scanner("x²");
+        assert_eq!(tokens[0].lexeme, "x");
+        assert!(matches!(
+            errors[..],
+            [ScanError::StrayUnicode {
+                character: '²', ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn stray_characters_report_their_column() {
+        let (_, errors) = scanner("\u{feff}var é = 1;\r\n  x € y;");
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ["[line 2] Error: Unexpected character '€' (U+20AC) at column 5."]
+        );
+    }
 }
@@ -0,0 +1,53 @@
+use std::rc::Rc;
+
+use crate::exprs::Expr;
+use crate::tokens::Token;
+
+/// A `fun` declaration. Shared behind an `Rc` so every closure created from
+/// it points at the same parsed body.
+pub struct FunctionDecl {
+    pub name: Token,
+    pub params: Vec<Token>,
+    pub body: Vec<Stmt>,
+    /// The `///` comment above the declaration, kept for documentation
+    /// tooling; nothing in the interpreter reads it.
+    #[allow(dead_code)]
+    pub doc: Option<String>,
+}
+
+#[derive(Clone)]
+pub enum Stmt {
+    Expression(Expr),
+    Print {
+        keyword: Token,
+        value: Expr,
+    },
+    Var {
+        name: Token,
+        initializer: Option<Expr>,
+        #[allow(dead_code)]
+        doc: Option<String>,
+    },
+    Block(Vec<Stmt>),
+    If {
+        condition: Expr,
+        then_branch: Box<Stmt>,
+        else_branch: Option<Box<Stmt>>,
+    },
+    While {
+        condition: Expr,
+        body: Box<Stmt>,
+    },
+    Function(Rc<FunctionDecl>),
+    Class {
+        name: Token,
+        superclass: Option<Expr>,
+        methods: Vec<Rc<FunctionDecl>>,
+        #[allow(dead_code)]
+        doc: Option<String>,
+    },
+    Return {
+        keyword: Token,
+        value: Option<Expr>,
+    },
+}
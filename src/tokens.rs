@@ -1,8 +1,9 @@
 use phf::phf_map;
 use strum_macros::Display;
 
-#[derive(Display, Clone)]
+#[derive(Display, Debug, Clone)]
 #[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+#[allow(clippy::enum_variant_names)]
 pub enum Operator {
     EqualEqual,
     BangEqual,
@@ -10,7 +11,7 @@ pub enum Operator {
     GreaterEqual,
 }
 
-#[derive(Display, Clone)]
+#[derive(Display, Debug, Clone)]
 #[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
 pub enum Keyword {
     And,
@@ -31,7 +32,7 @@ pub enum Keyword {
     While,
 }
 
-#[derive(Display, Clone)]
+#[derive(Display, Debug, Clone)]
 #[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
 pub enum TokenType {
     RightParen,
@@ -52,15 +53,11 @@ pub enum TokenType {
     Bang,
     Less,
     Greater,
+    #[allow(clippy::upper_case_acronyms)]
     EOF,
-    Unknown(String),
-    Comment,
     Slash,
     // Blank,
-    String {
-        string: String,
-        finished: bool,
-    },
+    String(String),
     Number(String),
     Identifier(String),
     #[strum(to_string = "{kw}")]
@@ -70,11 +67,60 @@ pub enum TokenType {
     },
 }
 
-#[derive(Clone)]
+/// Where a token or expression sits in the source: the byte range
+/// `start..end` and the 1-based column (in characters) of its first
+/// character on its line.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// The smallest span covering both `self` and the later span `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end.max(self.end),
+            column: self.column,
+        }
+    }
+}
+
+/// What a piece of [`Trivia`] is.
+#[derive(Display, Debug, Clone, Copy, PartialEq)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum TriviaKind {
+    /// Spaces, tabs and line breaks.
+    Whitespace,
+    LineComment,
+    DocComment,
+    BlockComment,
+    /// Input the scanner rejected, kept so the text can still be rebuilt.
+    Skipped,
+}
+
+/// Source text between tokens. Only recorded by the lossless scanner.
+#[derive(Debug, Clone)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub line: usize,
+    pub span: Span,
+    /// Text of the `///` doc comment lines directly before this token, joined
+    /// with newlines. Only meaningful on the first token of a declaration.
+    pub doc: Option<String>,
+    /// Everything between the previous token and this one, in order. Empty
+    /// unless the source was scanned in lossless mode.
+    pub leading_trivia: Vec<Trivia>,
 }
 
 pub static KEYWORDS: phf::Map<&'static str, Keyword> = phf_map! {
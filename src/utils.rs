@@ -1,18 +1,200 @@
-pub fn trim_string(to_split: &String) -> String {
+/// An escape sequence [`unescape`] could not decode, as a byte range of its
+/// input.
+pub struct InvalidEscape {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Decodes the escape sequences (`\"`, `\\`, `\n`, `\t`, `\r`, `\0` and
+/// `\u{XXXX}`) in the contents of a string literal, reporting every
+/// sequence that isn't one of them.
+pub fn unescape(body: &str) -> Result<String, Vec<InvalidEscape>> {
+    let mut decoded = String::with_capacity(body.len());
+    let mut invalid = Vec::new();
+    let mut chars = body.char_indices().peekable();
+
+    while let Some((start, char)) = chars.next() {
+        if char != '\\' {
+            decoded.push(char);
+            continue;
+        }
+        let escaped = match chars.next() {
+            Some((_, '"')) => Some('"'),
+            Some((_, '\\')) => Some('\\'),
+            Some((_, 'n')) => Some('\n'),
+            Some((_, 't')) => Some('\t'),
+            Some((_, 'r')) => Some('\r'),
+            Some((_, '0')) => Some('\0'),
+            Some((_, 'u')) if chars.next_if(|&(_, c)| c == '{').is_some() => {
+                let mut digits = String::new();
+                while let Some((_, c)) = chars.next_if(|&(_, c)| c.is_ascii_hexdigit()) {
+                    digits.push(c);
+                }
+                let closed = chars.next_if(|&(_, c)| c == '}').is_some();
+                (closed && (1..=6).contains(&digits.len()))
+                    .then(|| u32::from_str_radix(&digits, 16).ok())
+                    .flatten()
+                    .and_then(char::from_u32)
+            }
+            _ => None,
+        };
+        match escaped {
+            Some(escaped) => decoded.push(escaped),
+            None => invalid.push(InvalidEscape {
+                start,
+                end: chars.peek().map_or(body.len(), |&(i, _)| i),
+            }),
+        }
+    }
+
+    if invalid.is_empty() {
+        Ok(decoded)
+    } else {
+        Err(invalid)
+    }
+}
+
+/// The value of a string literal lexeme: the text between the quotes with
+/// its escape sequences decoded. The scanner has already rejected literals
+/// with invalid escapes.
+pub fn trim_string(to_split: &str) -> String {
     let length = to_split.len();
-    to_split[1..length - 1].to_string()
+    let body = &to_split[1..length - 1];
+    unescape(body).unwrap_or_else(|_| body.to_string())
+}
+
+/// Whether `group` is a run of digits in `radix`, with `_` allowed only
+/// between two digits.
+fn is_digit_group(group: &str, radix: u32) -> bool {
+    !group.is_empty()
+        && !group.starts_with('_')
+        && !group.ends_with('_')
+        && !group.contains("__")
+        && group.chars().all(|c| c == '_' || c.is_digit(radix))
+}
+
+/// The value of a number literal: decimal with optional fraction and
+/// exponent (`1_000.5e-3`), hexadecimal (`0xFF`) or binary (`0b1010`).
+/// Returns `None` if the literal is malformed.
+pub fn parse_number(literal: &str) -> Option<f64> {
+    let radix_digits = |digits: &str, radix: u32| {
+        is_digit_group(digits, radix).then(|| {
+            digits
+                .chars()
+                .filter_map(|c| c.to_digit(radix))
+                .fold(0.0, |value, digit| value * radix as f64 + digit as f64)
+        })
+    };
+    if let Some(digits) = literal.strip_prefix("0x").or(literal.strip_prefix("0X")) {
+        return radix_digits(digits, 16);
+    }
+    if let Some(digits) = literal.strip_prefix("0b").or(literal.strip_prefix("0B")) {
+        return radix_digits(digits, 2);
+    }
+
+    let (mantissa, exponent) = match literal.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+        None => (literal, None),
+    };
+    let (integer, fraction) = match mantissa.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (mantissa, None),
+    };
+    let valid = is_digit_group(integer, 10)
+        && fraction.is_none_or(|fraction| is_digit_group(fraction, 10))
+        && exponent.is_none_or(|exponent| {
+            is_digit_group(exponent.strip_prefix(['+', '-']).unwrap_or(exponent), 10)
+        });
+    valid
+        .then(|| literal.replace('_', "").parse().ok())
+        .flatten()
+}
+
+/// Which of the two conventions for printing numbers to follow.
+#[derive(Clone, Copy)]
+pub enum NumberStyle {
+    /// How `tokenize` and `parse` show literals: integral values keep a
+    /// fractional part, so `16`, `0x10` and `16.00` are all `16.0`.
+    Literal,
+    /// How `print` shows values at runtime: integral values drop it, `16`.
+    Value,
 }
 
-pub fn format_number_as_string(num_as_string: &String) -> String {
-    let mut new_string = num_as_string.clone();
-    if new_string.ends_with('.') {
-        new_string.push('0')
-    } else if !new_string.contains('.') {
-        new_string.push_str(".0")
-    } else if new_string.ends_with(".00") {
-        new_string = new_string.replace(".00", ".0");
+/// The one place numbers are turned into text. Non-integral values use the
+/// shortest form that reads back as the same number (`0.1`, `12.5`).
+pub fn format_number(value: f64, style: NumberStyle) -> String {
+    if value.is_infinite() {
+        return if value > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+    }
+    let text = format!("{value:?}");
+    match style {
+        NumberStyle::Literal => text,
+        NumberStyle::Value => text.strip_suffix(".0").unwrap_or(&text).to_string(),
     }
-    new_string
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The byte ranges `unescape` rejects in `body`, as text.
+    fn invalid(body: &str) -> Vec<&str> {
+        match unescape(body) {
+            Ok(_) => Vec::new(),
+            Err(invalid) => invalid
+                .iter()
+                .map(|escape| &body[escape.start..escape.end])
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn unescape_decodes_known_escapes() {
+        assert_eq!(
+            unescape(r#"a\"b\\c\nd\te\rf\0g"#).ok().as_deref(),
+            Some("a\"b\\c\nd\te\rf\0g")
+        );
+        assert_eq!(
+            unescape(r"\u{48}\u{e9}\u{1F600}\u{10FFFF}").ok().as_deref(),
+            Some("H\u{e9}\u{1F600}\u{10FFFF}")
+        );
+    }
+
+    #[test]
+    fn unescape_reports_every_invalid_escape() {
+        assert_eq!(invalid(r"\u{}"), [r"\u{}"]);
+        assert_eq!(invalid(r"\u{110000}"), [r"\u{110000}"]);
+        assert_eq!(invalid(r"\u{D800}"), [r"\u{D800}"]);
+        assert_eq!(invalid(r"\u{1234567}"), [r"\u{1234567}"]);
+        assert_eq!(invalid(r"\u{41"), [r"\u{41"]);
+        assert_eq!(invalid(r"\u41"), [r"\u"]);
+        assert_eq!(invalid(r"ok\q\x"), [r"\q", r"\x"]);
+        assert_eq!(invalid("trailing\\"), ["\\"]);
+        assert_eq!(invalid(r"\é"), [r"\é"]);
+    }
 
+    #[test]
+    fn parse_number_accepts_every_literal_form() {
+        assert_eq!(parse_number("0"), Some(0.0));
+        assert_eq!(parse_number("12.5"), Some(12.5));
+        assert_eq!(parse_number("1_000.000_5"), Some(1000.0005));
+        assert_eq!(parse_number("1e3"), Some(1000.0));
+        assert_eq!(parse_number("2.5E-1"), Some(0.25));
+        assert_eq!(parse_number("1e+2"), Some(100.0));
+        assert_eq!(parse_number("0xFF"), Some(255.0));
+        assert_eq!(parse_number("0Xff_ff"), Some(65535.0));
+        assert_eq!(parse_number("0b1010"), Some(10.0));
+        assert_eq!(parse_number("0B1_0"), Some(2.0));
+        assert_eq!(parse_number("1e999"), Some(f64::INFINITY));
+    }
+
+    #[test]
+    fn parse_number_rejects_malformed_literals() {
+        for literal in [
+            "1__0", "1_", "_1", "1._5", "0x", "0x_1", "0xG", "0b", "0b102", "12.", ".5", "1e",
+            "1e_3", "1e+", "1.2.3",
+        ] {
+            assert_eq!(parse_number(literal), None, "{literal}");
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::fmt::Display;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Deepest call nesting allowed before reporting a stack overflow, the
+/// script's own frame included. Both backends enforce it, so runaway
+/// recursion fails the same way in each.
+pub const FRAMES_MAX: usize = 10_000;
+
+/// A value as truthiness and equality see it. Both backends intern their
+/// strings, so strings are compared by pointer like every other heap value.
+pub enum Kind<'a> {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(&'a Rc<str>),
+    /// Any other heap value, identified by the address of its allocation.
+    Object(*const ()),
+}
+
+/// What the tree walker's and the VM's values have in common, so the parts
+/// of Lox that only depend on it are written once.
+pub trait LoxValue: Sized {
+    fn kind(&self) -> Kind<'_>;
+
+    fn number(value: f64) -> Self;
+
+    /// Lox truthiness: `nil` and `false` are falsey, everything else is
+    /// truthy.
+    fn is_truthy(&self) -> bool {
+        !matches!(self.kind(), Kind::Nil | Kind::Bool(false))
+    }
+
+    /// Lox equality, defined for every pair of values: values of different
+    /// kinds are never equal, and functions, classes and instances only
+    /// equal themselves.
+    fn is_equal(&self, other: &Self) -> bool {
+        match (self.kind(), other.kind()) {
+            (Kind::Nil, Kind::Nil) => true,
+            (Kind::Bool(a), Kind::Bool(b)) => a == b,
+            (Kind::Number(a), Kind::Number(b)) => a == b,
+            (Kind::String(a), Kind::String(b)) => Rc::ptr_eq(a, b),
+            (Kind::Object(a), Kind::Object(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// The address of an `Rc`'s allocation, as [`Kind::Object`] wants it.
+pub fn object<T: ?Sized>(rc: &Rc<T>) -> Kind<'static> {
+    Kind::Object(Rc::as_ptr(rc) as *const ())
+}
+
+/// A function implemented in Rust and exposed to Lox programs as a global.
+pub struct Native<V> {
+    pub name: &'static str,
+    pub arity: usize,
+    pub function: fn(&[V]) -> V,
+}
+
+impl<V> Display for Native<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<native fn>")
+    }
+}
+
+/// The globals every program starts with, for either backend.
+pub fn natives<V: LoxValue>() -> Vec<Native<V>> {
+    vec![Native {
+        name: "clock",
+        arity: 0,
+        function: |_| V::number(seconds_since_epoch()),
+    }]
+}
+
+fn seconds_since_epoch() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
@@ -0,0 +1,81 @@
+use super::object::Value;
+use crate::tokens::Span;
+
+/// One bytecode instruction. Operands follow the opcode byte in the code
+/// stream: constant and name indices and jump offsets take two bytes (big
+/// endian), local, upvalue and argument counts take one.
+#[derive(strum_macros::Display, strum_macros::FromRepr, Debug, Clone, Copy, PartialEq)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+#[repr(u8)]
+pub enum OpCode {
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    GetLocal,
+    SetLocal,
+    GetGlobal,
+    DefineGlobal,
+    SetGlobal,
+    GetUpvalue,
+    SetUpvalue,
+    GetProperty,
+    SetProperty,
+    GetSuper,
+    Equal,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    Jump,
+    JumpIfFalse,
+    Loop,
+    Call,
+    /// Followed by the function's constant index and then, for each of its
+    /// upvalues, an `is_local` byte and an index byte.
+    Closure,
+    CloseUpvalue,
+    Return,
+    Class,
+    Inherit,
+    Method,
+}
+
+/// A compiled function body: the code stream, the constants it refers to by
+/// index, and for every byte of code the line and span of the source it was
+/// compiled from, used to report runtime errors.
+#[derive(Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Value>,
+    pub lines: Vec<usize>,
+    pub spans: Vec<Span>,
+}
+
+impl Chunk {
+    pub fn write(&mut self, byte: u8, line: usize, span: Span) {
+        self.code.push(byte);
+        self.lines.push(line);
+        self.spans.push(span);
+    }
+
+    /// Returns the index of the new constant, or `None` if the table is full.
+    pub fn add_constant(&mut self, value: Value) -> Option<u16> {
+        let index = u16::try_from(self.constants.len()).ok()?;
+        self.constants.push(value);
+        Some(index)
+    }
+
+    /// Reads the two-byte operand starting at `offset`.
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.code[offset], self.code[offset + 1]])
+    }
+}
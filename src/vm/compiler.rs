@@ -0,0 +1,672 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::chunk::{Chunk, OpCode};
+use super::object::{Function, Value};
+use crate::errors::ParseError;
+use crate::evaluate::interner::Interner;
+use crate::exprs::Expr;
+use crate::stmts::{FunctionDecl, Stmt};
+use crate::tokens::{Keyword, Operator, Span, Token, TokenType};
+
+/// Operands of `GetLocal` and `GetUpvalue` are a single byte.
+const MAX_LOCALS: usize = 256;
+const MAX_UPVALUES: usize = 256;
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+struct Local {
+    name: String,
+    depth: usize,
+    /// Set when a closure captures the variable, so leaving its scope has to
+    /// move it off the stack.
+    is_captured: bool,
+}
+
+/// Where a closure finds one of its captured variables when it is created:
+/// a local slot of the enclosing function, or one of that function's own
+/// upvalues.
+#[derive(Clone, Copy, PartialEq)]
+struct UpvalueRef {
+    index: u8,
+    is_local: bool,
+}
+
+/// Where a variable lives, worked out at compile time.
+enum Slot {
+    Local(u8),
+    Upvalue(u8),
+    Global(u16),
+}
+
+/// State for one function being compiled. Declaring a function inside it
+/// pushes another on top.
+struct FunctionState {
+    function: Function,
+    kind: FunctionKind,
+    /// Stack slots of the function's frame, in order.
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueRef>,
+    scope_depth: usize,
+    /// Constant index of every name the function already refers to.
+    names: HashMap<String, u16>,
+}
+
+impl FunctionState {
+    fn new(name: &str, kind: FunctionKind) -> Self {
+        // Slot zero holds the function being called, or `this` in a method.
+        let receiver = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => "this",
+            FunctionKind::Script | FunctionKind::Function => "",
+        };
+        Self {
+            function: Function {
+                name: name.to_string(),
+                ..Function::default()
+            },
+            kind,
+            locals: vec![Local {
+                name: receiver.to_string(),
+                depth: 0,
+                is_captured: false,
+            }],
+            upvalues: Vec::new(),
+            scope_depth: 0,
+            names: HashMap::new(),
+        }
+    }
+
+    fn resolve_local(&self, name: &str) -> Option<usize> {
+        self.locals.iter().rposition(|local| local.name == name)
+    }
+}
+
+/// Compiles a resolved program to bytecode. Variables are resolved again
+/// here, to stack slots and upvalues rather than scope distances, following
+/// the same scoping rules as [`crate::resolver::resolver`].
+struct Compiler<'a> {
+    functions: Vec<FunctionState>,
+    errors: Vec<ParseError>,
+    /// The VM's interner: string constants are interned once, here, so
+    /// loading one is just a clone.
+    strings: &'a mut Interner,
+    /// The last token compiled, blamed for errors that have no token of
+    /// their own.
+    previous: Token,
+    /// Source location recorded against each byte emitted.
+    line: usize,
+    span: Span,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(strings: &'a mut Interner) -> Self {
+        Self {
+            functions: vec![FunctionState::new("", FunctionKind::Script)],
+            errors: Vec::new(),
+            strings,
+            previous: Token {
+                token_type: TokenType::EOF,
+                lexeme: String::new(),
+                line: 1,
+                span: Span::default(),
+                doc: None,
+                leading_trivia: Vec::new(),
+            },
+            line: 1,
+            span: Span::default(),
+        }
+    }
+
+    fn current(&mut self) -> &mut FunctionState {
+        self.functions
+            .last_mut()
+            .expect("the script is compiled in the outermost function")
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.current().function.chunk
+    }
+
+    fn error(&mut self, token: &Token, message: &str) {
+        self.errors.push(ParseError::new(token, message));
+    }
+
+    /// Attributes the code emitted next to `token`.
+    fn at(&mut self, token: &Token) {
+        self.at_span(token, token.span);
+    }
+
+    /// Attributes the code emitted next to `token`'s line but the wider
+    /// `span`, such as a whole binary expression.
+    fn at_span(&mut self, token: &Token, span: Span) {
+        self.previous = token.clone();
+        self.line = token.line;
+        self.span = span;
+    }
+
+    fn emit(&mut self, byte: u8) {
+        let (line, span) = (self.line, self.span);
+        self.chunk().write(byte, line, span);
+    }
+
+    fn emit_op(&mut self, op: OpCode) {
+        self.emit(op as u8);
+    }
+
+    fn emit_with_byte(&mut self, op: OpCode, operand: u8) {
+        self.emit_op(op);
+        self.emit(operand);
+    }
+
+    fn emit_with_u16(&mut self, op: OpCode, operand: u16) {
+        self.emit_op(op);
+        for byte in operand.to_be_bytes() {
+            self.emit(byte);
+        }
+    }
+
+    fn make_constant(&mut self, value: Value) -> u16 {
+        match self.chunk().add_constant(value) {
+            Some(index) => index,
+            None => {
+                let previous = self.previous.clone();
+                self.error(&previous, "Too many constants in one chunk.");
+                0
+            }
+        }
+    }
+
+    fn emit_constant(&mut self, value: Value) {
+        let index = self.make_constant(value);
+        self.emit_with_u16(OpCode::Constant, index);
+    }
+
+    fn identifier_constant(&mut self, name: &str) -> u16 {
+        if let Some(&index) = self.current().names.get(name) {
+            return index;
+        }
+        let interned = self.strings.intern(name);
+        let index = self.make_constant(Value::String(interned));
+        self.current().names.insert(name.to_string(), index);
+        index
+    }
+
+    /// Emits a jump with a placeholder offset and returns where the offset
+    /// goes, to be filled in by [`Compiler::patch_jump`].
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_with_u16(op, u16::MAX);
+        self.chunk().code.len() - 2
+    }
+
+    fn patch_jump(&mut self, offset: usize) {
+        let distance = self.chunk().code.len() - offset - 2;
+        let Ok(distance) = u16::try_from(distance) else {
+            let previous = self.previous.clone();
+            self.error(&previous, "Too much code to jump over.");
+            return;
+        };
+        let [high, low] = distance.to_be_bytes();
+        let code = &mut self.chunk().code;
+        code[offset] = high;
+        code[offset + 1] = low;
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) {
+        // The distance back also covers the `Loop` instruction itself.
+        let distance = self.chunk().code.len() + 3 - loop_start;
+        let distance = u16::try_from(distance).unwrap_or_else(|_| {
+            let previous = self.previous.clone();
+            self.error(&previous, "Loop body too large.");
+            0
+        });
+        self.emit_with_u16(OpCode::Loop, distance);
+    }
+
+    fn emit_return(&mut self) {
+        if self.current().kind == FunctionKind::Initializer {
+            self.emit_with_byte(OpCode::GetLocal, 0);
+        } else {
+            self.emit_op(OpCode::Nil);
+        }
+        self.emit_op(OpCode::Return);
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    /// Pops the scope's locals, moving captured ones off the stack.
+    fn end_scope(&mut self) {
+        let state = self.current();
+        state.scope_depth -= 1;
+        let mut ops = Vec::new();
+        while let Some(local) = state.locals.last() {
+            if local.depth <= state.scope_depth {
+                break;
+            }
+            ops.push(if local.is_captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            });
+            state.locals.pop();
+        }
+        for op in ops {
+            self.emit_op(op);
+        }
+    }
+
+    /// Claims the next stack slot of the current function for `name`.
+    fn add_local(&mut self, name: &str, token: &Token) {
+        let state = self.current();
+        if state.locals.len() == MAX_LOCALS {
+            self.error(token, "Too many local variables in function.");
+            return;
+        }
+        let depth = state.scope_depth;
+        state.locals.push(Local {
+            name: name.to_string(),
+            depth,
+            is_captured: false,
+        });
+    }
+
+    /// Binds the value on top of the stack to `name`: as a new local inside
+    /// a scope, otherwise as a global.
+    fn define_variable(&mut self, name: &Token) {
+        if self.current().scope_depth > 0 {
+            self.add_local(&name.lexeme, name);
+        } else {
+            let index = self.identifier_constant(&name.lexeme);
+            self.at(name);
+            self.emit_with_u16(OpCode::DefineGlobal, index);
+        }
+    }
+
+    fn add_upvalue(&mut self, function: usize, upvalue: UpvalueRef, token: &Token) -> u8 {
+        let upvalues = &mut self.functions[function].upvalues;
+        if let Some(existing) = upvalues.iter().position(|&other| other == upvalue) {
+            return existing as u8;
+        }
+        if upvalues.len() == MAX_UPVALUES {
+            self.error(token, "Too many closure variables in function.");
+            return 0;
+        }
+        upvalues.push(upvalue);
+        (upvalues.len() - 1) as u8
+    }
+
+    /// Finds `name` in the functions enclosing `function`, threading it
+    /// through an upvalue of every function in between.
+    fn resolve_upvalue(&mut self, function: usize, name: &str, token: &Token) -> Option<u8> {
+        let enclosing = function.checked_sub(1)?;
+        if let Some(slot) = self.functions[enclosing].resolve_local(name) {
+            self.functions[enclosing].locals[slot].is_captured = true;
+            let upvalue = UpvalueRef {
+                index: slot as u8,
+                is_local: true,
+            };
+            return Some(self.add_upvalue(function, upvalue, token));
+        }
+        let index = self.resolve_upvalue(enclosing, name, token)?;
+        let upvalue = UpvalueRef {
+            index,
+            is_local: false,
+        };
+        Some(self.add_upvalue(function, upvalue, token))
+    }
+
+    fn resolve(&mut self, name: &str, token: &Token) -> Slot {
+        let function = self.functions.len() - 1;
+        if let Some(slot) = self.functions[function].resolve_local(name) {
+            return Slot::Local(slot as u8);
+        }
+        match self.resolve_upvalue(function, name, token) {
+            Some(index) => Slot::Upvalue(index),
+            None => Slot::Global(self.identifier_constant(name)),
+        }
+    }
+
+    fn get_variable(&mut self, name: &str, token: &Token) {
+        let slot = self.resolve(name, token);
+        self.at(token);
+        match slot {
+            Slot::Local(slot) => self.emit_with_byte(OpCode::GetLocal, slot),
+            Slot::Upvalue(index) => self.emit_with_byte(OpCode::GetUpvalue, index),
+            Slot::Global(index) => self.emit_with_u16(OpCode::GetGlobal, index),
+        }
+    }
+
+    fn set_variable(&mut self, name: &str, token: &Token) {
+        let slot = self.resolve(name, token);
+        self.at(token);
+        match slot {
+            Slot::Local(slot) => self.emit_with_byte(OpCode::SetLocal, slot),
+            Slot::Upvalue(index) => self.emit_with_byte(OpCode::SetUpvalue, index),
+            Slot::Global(index) => self.emit_with_u16(OpCode::SetGlobal, index),
+        }
+    }
+
+    /// Compiles `declaration` as a function of its own and emits the
+    /// `Closure` instruction that creates it at runtime.
+    fn function(&mut self, declaration: &FunctionDecl, kind: FunctionKind) {
+        self.functions
+            .push(FunctionState::new(&declaration.name.lexeme, kind));
+        self.begin_scope();
+        for param in &declaration.params {
+            self.add_local(&param.lexeme, param);
+        }
+        self.statements(&declaration.body);
+        self.emit_return();
+
+        let state = self
+            .functions
+            .pop()
+            .expect("pushed at the start of this function");
+        let function = Function {
+            arity: declaration.params.len(),
+            upvalue_count: state.upvalues.len(),
+            ..state.function
+        };
+        self.at(&declaration.name);
+        let index = self.make_constant(Value::Function(Rc::new(function)));
+        self.emit_with_u16(OpCode::Closure, index);
+        for upvalue in state.upvalues {
+            self.emit(u8::from(upvalue.is_local));
+            self.emit(upvalue.index);
+        }
+    }
+
+    fn statements(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.statement(stmt);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression(expr) => {
+                // The `Pop` goes on the line the statement's code starts on.
+                let start = self.chunk().code.len();
+                self.expression(expr);
+                self.line = self.chunk().lines[start];
+                self.emit_op(OpCode::Pop);
+            }
+            Stmt::Print { keyword, value } => {
+                self.expression(value);
+                self.at(keyword);
+                self.emit_op(OpCode::Print);
+            }
+            Stmt::Var {
+                name, initializer, ..
+            } => {
+                self.at(name);
+                match initializer {
+                    Some(expr) => self.expression(expr),
+                    None => self.emit_op(OpCode::Nil),
+                }
+                self.define_variable(name);
+            }
+            Stmt::Block(statements) => {
+                self.begin_scope();
+                self.statements(statements);
+                self.end_scope();
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(condition);
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.statement(then_branch);
+                let end_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(else_jump);
+                self.emit_op(OpCode::Pop);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+                self.patch_jump(end_jump);
+            }
+            Stmt::While { condition, body } => {
+                let loop_start = self.chunk().code.len();
+                self.expression(condition);
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.statement(body);
+                self.emit_loop(loop_start);
+                self.patch_jump(exit_jump);
+                self.emit_op(OpCode::Pop);
+            }
+            Stmt::Function(declaration) => {
+                // Declared before the body is compiled so it can call itself.
+                if self.current().scope_depth > 0 {
+                    self.add_local(&declaration.name.lexeme, &declaration.name);
+                    self.function(declaration, FunctionKind::Function);
+                } else {
+                    self.function(declaration, FunctionKind::Function);
+                    self.define_variable(&declaration.name);
+                }
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+                ..
+            } => self.class(name, superclass.as_ref(), methods),
+            Stmt::Return { keyword, value } => {
+                match value {
+                    Some(value) => self.expression(value),
+                    None => {
+                        self.at(keyword);
+                        if self.current().kind == FunctionKind::Initializer {
+                            self.emit_with_byte(OpCode::GetLocal, 0);
+                        } else {
+                            self.emit_op(OpCode::Nil);
+                        }
+                    }
+                }
+                self.at(keyword);
+                self.emit_op(OpCode::Return);
+            }
+        }
+    }
+
+    /// Methods of a subclass capture its superclass from a scope of its own
+    /// holding `super`, like the tree walker's closures.
+    fn class(&mut self, name: &Token, superclass: Option<&Expr>, methods: &[Rc<FunctionDecl>]) {
+        let index = self.identifier_constant(&name.lexeme);
+        self.at(name);
+        self.emit_with_u16(OpCode::Class, index);
+        self.define_variable(name);
+
+        if let Some(superclass) = superclass {
+            self.expression(superclass);
+            self.begin_scope();
+            self.add_local("super", name);
+            self.get_variable(&name.lexeme, name);
+            if let Expr::Variable {
+                name: superclass_name,
+                ..
+            } = superclass
+            {
+                self.at(superclass_name);
+            }
+            self.emit_op(OpCode::Inherit);
+        }
+
+        self.get_variable(&name.lexeme, name);
+        for method in methods {
+            let kind = if method.name.lexeme == "init" {
+                FunctionKind::Initializer
+            } else {
+                FunctionKind::Method
+            };
+            self.function(method, kind);
+            let index = self.identifier_constant(&method.name.lexeme);
+            self.emit_with_u16(OpCode::Method, index);
+        }
+        self.emit_op(OpCode::Pop);
+
+        if superclass.is_some() {
+            self.end_scope();
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Bool(value, token) => {
+                self.at(token);
+                self.emit_op(if *value { OpCode::True } else { OpCode::False });
+            }
+            Expr::Nil(token) => {
+                self.at(token);
+                self.emit_op(OpCode::Nil);
+            }
+            Expr::Number(value, token) => {
+                self.at(token);
+                self.emit_constant(Value::Number(*value));
+            }
+            Expr::String(value, token) => {
+                self.at(token);
+                let value = self.strings.intern(value);
+                self.emit_constant(Value::String(value));
+            }
+            Expr::Grouping(inner, _) => self.expression(inner),
+            Expr::Unary { operator, right } => {
+                self.expression(right);
+                self.at_span(operator, expr.span());
+                match operator.token_type {
+                    TokenType::Minus => self.emit_op(OpCode::Negate),
+                    _ => self.emit_op(OpCode::Not),
+                }
+            }
+            Expr::Binary {
+                operator,
+                left,
+                right,
+            } => {
+                self.expression(left);
+                self.expression(right);
+                self.at_span(operator, expr.span());
+                match &operator.token_type {
+                    TokenType::Plus => self.emit_op(OpCode::Add),
+                    TokenType::Minus => self.emit_op(OpCode::Subtract),
+                    TokenType::Star => self.emit_op(OpCode::Multiply),
+                    TokenType::Slash => self.emit_op(OpCode::Divide),
+                    TokenType::Greater => self.emit_op(OpCode::Greater),
+                    TokenType::Less => self.emit_op(OpCode::Less),
+                    TokenType::Operator { op } => match op {
+                        Operator::EqualEqual => self.emit_op(OpCode::Equal),
+                        Operator::BangEqual => {
+                            self.emit_op(OpCode::Equal);
+                            self.emit_op(OpCode::Not);
+                        }
+                        Operator::GreaterEqual => self.emit_op(OpCode::GreaterEqual),
+                        Operator::LessEqual => self.emit_op(OpCode::LessEqual),
+                    },
+                    _ => unreachable!("the parser only builds binary expressions from operators"),
+                }
+            }
+            Expr::Logical {
+                operator,
+                left,
+                right,
+            } => {
+                self.expression(left);
+                self.at(operator);
+                let is_or = matches!(
+                    operator.token_type,
+                    TokenType::Keyword {
+                        kw: Keyword::Or,
+                        ..
+                    }
+                );
+                if is_or {
+                    let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                    let end_jump = self.emit_jump(OpCode::Jump);
+                    self.patch_jump(else_jump);
+                    self.emit_op(OpCode::Pop);
+                    self.expression(right);
+                    self.patch_jump(end_jump);
+                } else {
+                    let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+                    self.emit_op(OpCode::Pop);
+                    self.expression(right);
+                    self.patch_jump(end_jump);
+                }
+            }
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+            } => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+                self.at(paren);
+                // The parser rejects calls with more than 255 arguments.
+                self.emit_with_byte(OpCode::Call, arguments.len() as u8);
+            }
+            Expr::Get { object, name } => {
+                self.expression(object);
+                let index = self.identifier_constant(&name.lexeme);
+                self.at(name);
+                self.emit_with_u16(OpCode::GetProperty, index);
+            }
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => {
+                self.expression(object);
+                self.expression(value);
+                let index = self.identifier_constant(&name.lexeme);
+                self.at(name);
+                self.emit_with_u16(OpCode::SetProperty, index);
+            }
+            Expr::This { keyword, .. } => self.get_variable("this", keyword),
+            Expr::Super {
+                keyword, method, ..
+            } => {
+                self.get_variable("this", keyword);
+                self.get_variable("super", keyword);
+                let index = self.identifier_constant(&method.lexeme);
+                self.at(method);
+                self.emit_with_u16(OpCode::GetSuper, index);
+            }
+            Expr::Variable { name, .. } => self.get_variable(&name.lexeme, name),
+            Expr::Assign { name, value, .. } => {
+                self.expression(value);
+                self.set_variable(&name.lexeme, name);
+            }
+        }
+    }
+}
+
+/// Compiles a program that has already passed the resolver into the
+/// top-level script function. The only errors left to report are the
+/// bytecode's own limits, such as the number of locals in a function.
+/// String constants are interned in `strings`, which the VM running the
+/// script must use too.
+pub fn compile(stmts: &[Stmt], strings: &mut Interner) -> Result<Function, Vec<ParseError>> {
+    let mut compiler = Compiler::new(strings);
+    compiler.statements(stmts);
+    compiler.emit_return();
+    if !compiler.errors.is_empty() {
+        return Err(compiler.errors);
+    }
+    let script = compiler
+        .functions
+        .pop()
+        .expect("the script is compiled in the outermost function");
+    Ok(script.function)
+}
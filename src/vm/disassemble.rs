@@ -0,0 +1,118 @@
+use super::chunk::{Chunk, OpCode};
+use super::object::{Function, Value};
+
+/// Prints the chunk of `function` and then, depth first, those of the
+/// functions declared in it, each under a `== <fn name> ==` header.
+pub fn disassemble_function(function: &Function) {
+    println!("== {function} ==");
+    let chunk = &function.chunk;
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let (instruction, next) = disassemble_instruction(chunk, offset);
+        println!("{instruction}");
+        offset = next;
+    }
+    for constant in &chunk.constants {
+        if let Value::Function(inner) = constant {
+            println!();
+            disassemble_function(inner);
+        }
+    }
+}
+
+/// Renders the instruction at `offset` as its offset, its source line (`|`
+/// when it's the same as the previous instruction's), its name and its
+/// operands, and returns it with the offset of the next instruction.
+pub fn disassemble_instruction(chunk: &Chunk, offset: usize) -> (String, usize) {
+    let line = if offset > 0 && chunk.lines[offset] == chunk.lines[offset - 1] {
+        "   |".to_string()
+    } else {
+        format!("{:4}", chunk.lines[offset])
+    };
+    let prefix = format!("{offset:04} {line} ");
+    let byte = chunk.code[offset];
+    let Some(op) = OpCode::from_repr(byte) else {
+        return (format!("{prefix}Unknown opcode {byte}"), offset + 1);
+    };
+    let name = format!("OP_{op}");
+    match op {
+        OpCode::Constant
+        | OpCode::GetGlobal
+        | OpCode::DefineGlobal
+        | OpCode::SetGlobal
+        | OpCode::GetProperty
+        | OpCode::SetProperty
+        | OpCode::GetSuper
+        | OpCode::Class
+        | OpCode::Method => {
+            let index = chunk.read_u16(offset + 1);
+            let constant = &chunk.constants[usize::from(index)];
+            (
+                format!("{prefix}{name:<16} {index:4} '{constant}'"),
+                offset + 3,
+            )
+        }
+        OpCode::GetLocal
+        | OpCode::SetLocal
+        | OpCode::GetUpvalue
+        | OpCode::SetUpvalue
+        | OpCode::Call => {
+            let operand = chunk.code[offset + 1];
+            (format!("{prefix}{name:<16} {operand:4}"), offset + 2)
+        }
+        OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop => {
+            let distance = usize::from(chunk.read_u16(offset + 1));
+            let target = if op == OpCode::Loop {
+                offset + 3 - distance
+            } else {
+                offset + 3 + distance
+            };
+            (
+                format!("{prefix}{name:<16} {offset:4} -> {target}"),
+                offset + 3,
+            )
+        }
+        OpCode::Closure => {
+            let index = chunk.read_u16(offset + 1);
+            let constant = &chunk.constants[usize::from(index)];
+            let mut text = format!("{prefix}{name:<16} {index:4} {constant}");
+            let upvalue_count = match constant {
+                Value::Function(function) => function.upvalue_count,
+                _ => 0,
+            };
+            let mut next = offset + 3;
+            for _ in 0..upvalue_count {
+                let kind = if chunk.code[next] == 1 {
+                    "local"
+                } else {
+                    "upvalue"
+                };
+                let index = chunk.code[next + 1];
+                text.push_str(&format!(
+                    "\n{next:04}    |                     {kind} {index}"
+                ));
+                next += 2;
+            }
+            (text, next)
+        }
+        OpCode::Nil
+        | OpCode::True
+        | OpCode::False
+        | OpCode::Pop
+        | OpCode::Equal
+        | OpCode::Greater
+        | OpCode::GreaterEqual
+        | OpCode::Less
+        | OpCode::LessEqual
+        | OpCode::Add
+        | OpCode::Subtract
+        | OpCode::Multiply
+        | OpCode::Divide
+        | OpCode::Not
+        | OpCode::Negate
+        | OpCode::Print
+        | OpCode::CloseUpvalue
+        | OpCode::Return
+        | OpCode::Inherit => (format!("{prefix}{name}"), offset + 1),
+    }
+}
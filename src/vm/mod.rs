@@ -0,0 +1,6 @@
+pub mod chunk;
+pub mod compiler;
+pub mod disassemble;
+pub mod object;
+#[allow(clippy::module_inception)]
+pub mod vm;
@@ -0,0 +1,117 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::rc::Rc;
+
+use super::chunk::Chunk;
+use crate::utils::{format_number, NumberStyle};
+use crate::values::{object, Kind, LoxValue, Native};
+
+/// A value on the VM's stack. Like the tree walker's values, everything but
+/// `nil`, booleans and numbers is reference counted, and prints the same way.
+#[derive(Clone)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(Rc<str>),
+    /// Only ever a constant operand of [`super::chunk::OpCode::Closure`].
+    Function(Rc<Function>),
+    Closure(Rc<Closure>),
+    Native(Rc<Native<Value>>),
+    Class(Rc<RefCell<Class>>),
+    Instance(Rc<RefCell<Instance>>),
+    BoundMethod(Rc<BoundMethod>),
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => f.write_str(&format_number(*n, NumberStyle::Value)),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Function(function) => write!(f, "{}", function),
+            Value::Closure(closure) => write!(f, "{}", closure.function),
+            Value::Native(_) => f.write_str("<native fn>"),
+            Value::Class(class) => f.write_str(&class.borrow().name),
+            Value::Instance(instance) => {
+                write!(f, "{} instance", instance.borrow().class.borrow().name)
+            }
+            Value::BoundMethod(bound) => write!(f, "{}", bound.method.function),
+        }
+    }
+}
+
+impl LoxValue for Value {
+    fn kind(&self) -> Kind<'_> {
+        match self {
+            Value::Nil => Kind::Nil,
+            Value::Bool(b) => Kind::Bool(*b),
+            Value::Number(n) => Kind::Number(*n),
+            Value::String(s) => Kind::String(s),
+            Value::Function(function) => object(function),
+            Value::Closure(closure) => object(closure),
+            Value::Native(native) => object(native),
+            Value::Class(class) => object(class),
+            Value::Instance(instance) => object(instance),
+            Value::BoundMethod(bound) => object(bound),
+        }
+    }
+
+    fn number(value: f64) -> Self {
+        Value::Number(value)
+    }
+}
+
+/// A compiled function. The top-level script is one too, with an empty
+/// name.
+#[derive(Default)]
+pub struct Function {
+    pub name: String,
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.name.is_empty() {
+            f.write_str("<script>")
+        } else {
+            write!(f, "<fn {}>", self.name)
+        }
+    }
+}
+
+/// A variable captured by a closure. It points at the variable's stack slot
+/// while the variable is in scope, and holds the value itself once the
+/// variable's scope ends.
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+/// A function together with the variables it captured when it was created.
+pub struct Closure {
+    pub function: Rc<Function>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+/// A class's methods include those inherited from its superclass, which are
+/// copied in when the class is declared.
+pub struct Class {
+    pub name: String,
+    pub methods: HashMap<Rc<str>, Rc<Closure>>,
+}
+
+pub struct Instance {
+    pub class: Rc<RefCell<Class>>,
+    pub fields: HashMap<Rc<str>, Value>,
+}
+
+/// A method read off an instance, remembering the instance as `this`.
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: Rc<Closure>,
+}
@@ -0,0 +1,509 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::chunk::{Chunk, OpCode};
+use super::disassemble::disassemble_instruction;
+use super::object::{BoundMethod, Class, Closure, Function, Instance, Upvalue, Value};
+use crate::errors::RuntimeError;
+use crate::evaluate::interner::Interner;
+use crate::values::{natives, LoxValue, FRAMES_MAX};
+
+struct CallFrame {
+    closure: Rc<Closure>,
+    /// Offset of the next byte to execute in the closure's chunk.
+    ip: usize,
+    /// Stack index of the frame's slot zero: the callee, or the receiver of
+    /// a method call, followed by the arguments and locals.
+    slots: usize,
+}
+
+/// A stack-based virtual machine running the bytecode produced by
+/// [`super::compiler::compile`]. Its output, runtime errors included, is the
+/// same as the tree walker's.
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: HashMap<Rc<str>, Value>,
+    /// Upvalues still pointing into the stack, ordered by slot.
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    /// Strings are interned as the tree walker's are, so equal strings are
+    /// one allocation and compare by pointer.
+    strings: Interner,
+    /// Print the stack and the next instruction to stderr before executing
+    /// each one.
+    trace: bool,
+}
+
+impl Vm {
+    /// `strings` must be the interner the script was compiled with.
+    pub fn new(strings: Interner, trace: bool) -> Self {
+        let globals = natives()
+            .into_iter()
+            .map(|native| (Rc::from(native.name), Value::Native(Rc::new(native))))
+            .collect();
+        Self {
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals,
+            open_upvalues: Vec::new(),
+            strings,
+            trace,
+        }
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames
+            .last()
+            .expect("the script frame is always present")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames
+            .last_mut()
+            .expect("the script frame is always present")
+    }
+
+    fn chunk(&self) -> &Chunk {
+        &self.frame().closure.function.chunk
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame_mut();
+        let byte = frame.closure.function.chunk.code[frame.ip];
+        frame.ip += 1;
+        byte
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        let frame = self.frame_mut();
+        let value = frame.closure.function.chunk.read_u16(frame.ip);
+        frame.ip += 2;
+        value
+    }
+
+    fn read_constant(&mut self) -> Value {
+        let index = usize::from(self.read_u16());
+        self.chunk().constants[index].clone()
+    }
+
+    fn read_string(&mut self) -> Rc<str> {
+        match self.read_constant() {
+            Value::String(name) => name,
+            _ => unreachable!("the compiler only emits names as string constants"),
+        }
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack
+            .pop()
+            .expect("the compiler keeps the stack balanced")
+    }
+
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    /// An error at the instruction being executed, with a stack trace
+    /// covering every active call.
+    fn error(&self, message: impl Into<String>) -> RuntimeError {
+        let location = self.frame().ip - 1;
+        let chunk = self.chunk();
+        let mut error =
+            RuntimeError::at_line(chunk.lines[location], chunk.spans[location], message);
+        for (callee, caller) in self
+            .frames
+            .iter()
+            .rev()
+            .zip(self.frames.iter().rev().skip(1))
+        {
+            let call_line = caller.closure.function.chunk.lines[caller.ip - 1];
+            error.unwind(&callee.closure.function.name, call_line);
+        }
+        error
+    }
+
+    fn number_operands(&mut self) -> Result<(f64, f64), RuntimeError> {
+        match (self.peek(1), self.peek(0)) {
+            (Value::Number(a), Value::Number(b)) => {
+                let operands = (*a, *b);
+                self.stack.truncate(self.stack.len() - 2);
+                Ok(operands)
+            }
+            _ => Err(self.error("Operands must be numbers.")),
+        }
+    }
+
+    fn call(&mut self, closure: Rc<Closure>, arg_count: usize) -> Result<(), RuntimeError> {
+        if arg_count != closure.function.arity {
+            return Err(self.error(format!(
+                "Expected {} arguments but got {}.",
+                closure.function.arity, arg_count
+            )));
+        }
+        if self.frames.len() == FRAMES_MAX {
+            return Err(self.error("Stack overflow."));
+        }
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            slots: self.stack.len() - arg_count - 1,
+        });
+        Ok(())
+    }
+
+    fn call_value(&mut self, callee: Value, arg_count: usize) -> Result<(), RuntimeError> {
+        let slot = self.stack.len() - arg_count - 1;
+        match callee {
+            Value::Closure(closure) => self.call(closure, arg_count),
+            Value::Native(native) => {
+                if arg_count != native.arity {
+                    return Err(self.error(format!(
+                        "Expected {} arguments but got {}.",
+                        native.arity, arg_count
+                    )));
+                }
+                let result = (native.function)(&self.stack[slot + 1..]);
+                self.stack.truncate(slot);
+                self.push(result);
+                Ok(())
+            }
+            Value::Class(class) => {
+                let initializer = class.borrow().methods.get("init").cloned();
+                self.stack[slot] = Value::Instance(Rc::new(RefCell::new(Instance {
+                    class,
+                    fields: HashMap::new(),
+                })));
+                match initializer {
+                    Some(initializer) => self.call(initializer, arg_count),
+                    None if arg_count != 0 => {
+                        Err(self.error(format!("Expected 0 arguments but got {}.", arg_count)))
+                    }
+                    None => Ok(()),
+                }
+            }
+            Value::BoundMethod(bound) => {
+                self.stack[slot] = bound.receiver.clone();
+                self.call(Rc::clone(&bound.method), arg_count)
+            }
+            _ => Err(self.error("Can only call functions and classes.")),
+        }
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let position = self
+            .open_upvalues
+            .iter()
+            .position(|upvalue| matches!(*upvalue.borrow(), Upvalue::Open(open) if open >= slot));
+        if let Some(position) = position {
+            let existing = &self.open_upvalues[position];
+            if matches!(*existing.borrow(), Upvalue::Open(open) if open == slot) {
+                return Rc::clone(existing);
+            }
+        }
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        let position = position.unwrap_or(self.open_upvalues.len());
+        self.open_upvalues.insert(position, Rc::clone(&upvalue));
+        upvalue
+    }
+
+    /// Moves every captured variable at or above stack index `from` off the
+    /// stack and into its upvalue.
+    fn close_upvalues(&mut self, from: usize) {
+        while let Some(upvalue) = self.open_upvalues.last() {
+            let slot = match *upvalue.borrow() {
+                Upvalue::Open(slot) if slot >= from => slot,
+                _ => break,
+            };
+            *upvalue.borrow_mut() = Upvalue::Closed(self.stack[slot].clone());
+            self.open_upvalues.pop();
+        }
+    }
+
+    fn bind_method(
+        &self,
+        class: &Rc<RefCell<Class>>,
+        receiver: Value,
+        name: &str,
+    ) -> Result<Value, RuntimeError> {
+        match class.borrow().methods.get(name) {
+            Some(method) => Ok(Value::BoundMethod(Rc::new(BoundMethod {
+                receiver,
+                method: Rc::clone(method),
+            }))),
+            None => Err(self.error(format!("Undefined property '{}'.", name))),
+        }
+    }
+
+    fn trace_instruction(&self) {
+        let stack: String = self
+            .stack
+            .iter()
+            .map(|value| format!("[ {value} ]"))
+            .collect();
+        eprintln!("          {stack}");
+        let (instruction, _) = disassemble_instruction(self.chunk(), self.frame().ip);
+        eprintln!("{instruction}");
+    }
+
+    fn run(&mut self) -> Result<(), RuntimeError> {
+        loop {
+            if self.trace {
+                self.trace_instruction();
+            }
+            let byte = self.read_byte();
+            let op = OpCode::from_repr(byte).expect("the compiler only emits valid opcodes");
+            match op {
+                OpCode::Constant => {
+                    let constant = self.read_constant();
+                    self.push(constant);
+                }
+                OpCode::Nil => self.push(Value::Nil),
+                OpCode::True => self.push(Value::Bool(true)),
+                OpCode::False => self.push(Value::Bool(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = self.frame().slots + usize::from(self.read_byte());
+                    self.push(self.stack[slot].clone());
+                }
+                OpCode::SetLocal => {
+                    let slot = self.frame().slots + usize::from(self.read_byte());
+                    self.stack[slot] = self.peek(0).clone();
+                }
+                OpCode::GetGlobal => {
+                    let name = self.read_string();
+                    match self.globals.get(&name) {
+                        Some(value) => self.push(value.clone()),
+                        None => return Err(self.error(format!("Undefined variable '{}'.", name))),
+                    }
+                }
+                OpCode::DefineGlobal => {
+                    let name = self.read_string();
+                    let value = self.pop();
+                    self.globals.insert(name, value);
+                }
+                OpCode::SetGlobal => {
+                    let name = self.read_string();
+                    let value = self.peek(0).clone();
+                    match self.globals.get_mut(&name) {
+                        Some(slot) => *slot = value,
+                        None => return Err(self.error(format!("Undefined variable '{}'.", name))),
+                    }
+                }
+                OpCode::GetUpvalue => {
+                    let index = usize::from(self.read_byte());
+                    let upvalue = Rc::clone(&self.frame().closure.upvalues[index]);
+                    let value = match &*upvalue.borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    self.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let index = usize::from(self.read_byte());
+                    let upvalue = Rc::clone(&self.frame().closure.upvalues[index]);
+                    let value = self.peek(0).clone();
+                    let mut upvalue = upvalue.borrow_mut();
+                    match &mut *upvalue {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                OpCode::GetProperty => {
+                    let name = self.read_string();
+                    let Value::Instance(instance) = self.peek(0).clone() else {
+                        return Err(self.error("Only instances have properties."));
+                    };
+                    // Fields shadow methods of the same name.
+                    let field = instance.borrow().fields.get(&name).cloned();
+                    let value = match field {
+                        Some(value) => value,
+                        None => {
+                            let class = Rc::clone(&instance.borrow().class);
+                            self.bind_method(&class, Value::Instance(instance), &name)?
+                        }
+                    };
+                    self.pop();
+                    self.push(value);
+                }
+                OpCode::SetProperty => {
+                    let name = self.read_string();
+                    let Value::Instance(instance) = self.peek(1).clone() else {
+                        return Err(self.error("Only instances have fields."));
+                    };
+                    let value = self.pop();
+                    instance.borrow_mut().fields.insert(name, value.clone());
+                    self.pop();
+                    self.push(value);
+                }
+                OpCode::GetSuper => {
+                    let name = self.read_string();
+                    let Value::Class(superclass) = self.pop() else {
+                        unreachable!("`super` is always bound to a class")
+                    };
+                    let receiver = self.pop();
+                    let method = self.bind_method(&superclass, receiver, &name)?;
+                    self.push(method);
+                }
+                OpCode::Equal => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.push(Value::Bool(left.is_equal(&right)));
+                }
+                OpCode::Greater => {
+                    let (a, b) = self.number_operands()?;
+                    self.push(Value::Bool(a > b));
+                }
+                OpCode::GreaterEqual => {
+                    let (a, b) = self.number_operands()?;
+                    self.push(Value::Bool(a >= b));
+                }
+                OpCode::Less => {
+                    let (a, b) = self.number_operands()?;
+                    self.push(Value::Bool(a < b));
+                }
+                OpCode::LessEqual => {
+                    let (a, b) = self.number_operands()?;
+                    self.push(Value::Bool(a <= b));
+                }
+                OpCode::Add => {
+                    let sum = match (self.peek(1), self.peek(0)) {
+                        (Value::Number(a), Value::Number(b)) => Value::Number(a + b),
+                        (Value::String(a), Value::String(b)) => {
+                            Value::String(self.strings.intern(&format!("{a}{b}")))
+                        }
+                        _ => return Err(self.error("Operands must be two numbers or two strings.")),
+                    };
+                    self.stack.truncate(self.stack.len() - 2);
+                    self.push(sum);
+                }
+                OpCode::Subtract => {
+                    let (a, b) = self.number_operands()?;
+                    self.push(Value::Number(a - b));
+                }
+                OpCode::Multiply => {
+                    let (a, b) = self.number_operands()?;
+                    self.push(Value::Number(a * b));
+                }
+                OpCode::Divide => {
+                    let (a, b) = self.number_operands()?;
+                    self.push(Value::Number(a / b));
+                }
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.push(Value::Bool(!value.is_truthy()));
+                }
+                OpCode::Negate => match self.peek(0) {
+                    Value::Number(n) => {
+                        let negated = Value::Number(-n);
+                        self.pop();
+                        self.push(negated);
+                    }
+                    _ => return Err(self.error("Operand must be a number.")),
+                },
+                OpCode::Print => println!("{}", self.pop()),
+                OpCode::Jump => {
+                    let offset = usize::from(self.read_u16());
+                    self.frame_mut().ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = usize::from(self.read_u16());
+                    if !self.peek(0).is_truthy() {
+                        self.frame_mut().ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = usize::from(self.read_u16());
+                    self.frame_mut().ip -= offset;
+                }
+                OpCode::Call => {
+                    let arg_count = usize::from(self.read_byte());
+                    let callee = self.peek(arg_count).clone();
+                    self.call_value(callee, arg_count)?;
+                }
+                OpCode::Closure => {
+                    let Value::Function(function) = self.read_constant() else {
+                        unreachable!("the compiler only emits closures over functions")
+                    };
+                    let mut upvalues = Vec::with_capacity(function.upvalue_count);
+                    for _ in 0..function.upvalue_count {
+                        let is_local = self.read_byte() == 1;
+                        let index = usize::from(self.read_byte());
+                        upvalues.push(if is_local {
+                            self.capture_upvalue(self.frame().slots + index)
+                        } else {
+                            Rc::clone(&self.frame().closure.upvalues[index])
+                        });
+                    }
+                    self.push(Value::Closure(Rc::new(Closure { function, upvalues })));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self
+                        .frames
+                        .pop()
+                        .expect("the script frame is always present");
+                    self.close_upvalues(frame.slots);
+                    self.stack.truncate(frame.slots);
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    self.push(result);
+                }
+                OpCode::Class => {
+                    let name = self.read_string();
+                    self.push(Value::Class(Rc::new(RefCell::new(Class {
+                        name: name.to_string(),
+                        methods: HashMap::new(),
+                    }))));
+                }
+                OpCode::Inherit => {
+                    let Value::Class(superclass) = self.peek(1) else {
+                        return Err(self.error("Superclass must be a class."));
+                    };
+                    let methods = superclass.borrow().methods.clone();
+                    if let Value::Class(class) = self.peek(0) {
+                        class.borrow_mut().methods.extend(methods);
+                    }
+                    self.pop();
+                }
+                OpCode::Method => {
+                    let name = self.read_string();
+                    let Value::Closure(method) = self.pop() else {
+                        unreachable!("methods are always compiled to closures")
+                    };
+                    if let Value::Class(class) = self.peek(0) {
+                        class.borrow_mut().methods.insert(name, method);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Runs a compiled script to completion with the interner it was compiled
+/// with, tracing every instruction to stderr if `trace` is set.
+pub fn run_script(script: Function, strings: Interner, trace: bool) -> Result<(), RuntimeError> {
+    let mut vm = Vm::new(strings, trace);
+    let closure = Rc::new(Closure {
+        function: Rc::new(script),
+        upvalues: Vec::new(),
+    });
+    vm.push(Value::Closure(Rc::clone(&closure)));
+    vm.frames.push(CallFrame {
+        closure,
+        ip: 0,
+        slots: 0,
+    });
+    vm.run()
+}
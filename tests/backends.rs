@@ -0,0 +1,103 @@
+//! Runs every program in `tests/fixtures/run` through both backends and
+//! checks they agree on everything observable: stdout, stderr (in both
+//! error formats) and the exit code. Each program's `.expected` file pins
+//! its exit code, stdout and plain stderr:
+//!
+//! ```text
+//! exit: 70
+//! --- stdout
+//! ...
+//! --- stderr
+//! ...
+//! ```
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn run(path: &Path, backend: &str, error_format: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_interpreter-starter-rust"))
+        .arg("run")
+        .arg(path)
+        .args(["--backend", backend, "--error-format", error_format])
+        .output()
+        .expect("failed to run the interpreter")
+}
+
+/// The exit code, stdout and stderr an `.expected` file describes.
+fn expected(path: &Path) -> (i32, String, String) {
+    let expected = fs::read_to_string(path.with_extension("expected"))
+        .unwrap_or_else(|error| panic!("no expected output for {}: {error}", path.display()));
+    let (status, rest) = expected
+        .strip_prefix("exit: ")
+        .and_then(|rest| rest.split_once("\n--- stdout\n"))
+        .unwrap_or_else(|| panic!("malformed expected output for {}", path.display()));
+    let (stdout, stderr) = rest
+        .split_once("--- stderr\n")
+        .unwrap_or_else(|| panic!("malformed expected output for {}", path.display()));
+    (
+        status.parse().unwrap(),
+        stdout.to_string(),
+        stderr.to_string(),
+    )
+}
+
+#[test]
+fn backends_match_expected_output() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/run");
+    let mut checked = 0;
+    for entry in fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|extension| extension != "lox") {
+            continue;
+        }
+        let (status, stdout, stderr) = expected(&path);
+        for backend in ["tree", "vm"] {
+            let output = run(&path, backend, "plain");
+            let name = format!("{} ({backend})", path.display());
+            assert_eq!(
+                String::from_utf8_lossy(&output.stdout),
+                stdout,
+                "stdout of {name}"
+            );
+            assert_eq!(
+                String::from_utf8_lossy(&output.stderr),
+                stderr,
+                "stderr of {name}"
+            );
+            assert_eq!(output.status.code(), Some(status), "exit code of {name}");
+        }
+        checked += 1;
+    }
+    assert!(checked > 0, "no fixtures in {}", dir.display());
+}
+
+#[test]
+fn backends_agree_on_fixtures() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/run");
+    let mut checked = 0;
+    for entry in fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|extension| extension != "lox") {
+            continue;
+        }
+        for error_format in ["plain", "rich"] {
+            let tree = run(&path, "tree", error_format);
+            let vm = run(&path, "vm", error_format);
+            let name = format!("{} ({error_format})", path.display());
+            assert_eq!(
+                String::from_utf8_lossy(&tree.stdout),
+                String::from_utf8_lossy(&vm.stdout),
+                "stdout of {name}"
+            );
+            assert_eq!(
+                String::from_utf8_lossy(&tree.stderr),
+                String::from_utf8_lossy(&vm.stderr),
+                "stderr of {name}"
+            );
+            assert_eq!(tree.status.code(), vm.status.code(), "exit code of {name}");
+        }
+        checked += 1;
+    }
+    assert!(checked > 0, "no fixtures in {}", dir.display());
+}
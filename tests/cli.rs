@@ -0,0 +1,318 @@
+//! Command line handling: misuse is reported with the usage text and exit
+//! code 64 before any file is read, a file that can't be read exits 66, and
+//! `tokenize --format json|ndjson` prints machine-readable tokens.
+
+use std::process::{Command, Output};
+
+fn interpreter(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_interpreter-starter-rust"))
+        .args(args)
+        .output()
+        .expect("failed to run the interpreter")
+}
+
+const FIXTURE: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/run/closures.lox"
+);
+
+fn assert_usage_error(args: &[&str], message: &str) {
+    let output = interpreter(args);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(64), "{args:?}");
+    assert!(stderr.starts_with(message), "{args:?}: {stderr}");
+    assert!(stderr.contains("Usage:"), "{args:?}: {stderr}");
+    assert!(output.stdout.is_empty(), "{args:?}");
+}
+
+#[test]
+fn rejects_misuse() {
+    assert_usage_error(&[], "Usage:");
+    assert_usage_error(&["run"], "Usage:");
+    assert_usage_error(&["doc", FIXTURE], "Unknown command: doc");
+    assert_usage_error(
+        &["run", FIXTURE, "--backed", "vm"],
+        "Unknown option: --backed",
+    );
+    assert_usage_error(
+        &["run", FIXTURE, "--backend"],
+        "Missing value for --backend",
+    );
+    assert_usage_error(&["tokenize", FIXTURE, "--trace"], "Unknown option: --trace");
+    assert_usage_error(
+        &["parse", FIXTURE, "--format", "json"],
+        "Unknown option: --format",
+    );
+}
+
+#[test]
+fn unreadable_file_exits_66() {
+    for args in [
+        ["run", "tests/fixtures/missing.lox"],
+        ["tokenize", env!("CARGO_MANIFEST_DIR")],
+    ] {
+        let output = interpreter(&args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(66), "{args:?}");
+        assert!(
+            stderr.starts_with("Failed to read file"),
+            "{args:?}: {stderr}"
+        );
+        assert!(output.stdout.is_empty(), "{args:?}");
+    }
+}
+
+#[test]
+fn usage_lists_every_command_and_flag() {
+    let output = interpreter(&[]);
+    let usage = String::from_utf8_lossy(&output.stderr);
+    for word in [
+        "tokenize",
+        "parse",
+        "evaluate",
+        "run",
+        "disassemble",
+        "cst",
+        "--format",
+        "--error-format",
+        "--backend",
+        "--trace",
+        "--gc-stress",
+        "--gc-stats",
+    ] {
+        assert!(usage.contains(word), "usage is missing {word}");
+    }
+}
+
+/// A parsed JSON value, just enough to check the tokenizer's output.
+#[derive(Debug, PartialEq)]
+enum Json {
+    Null,
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn parse(text: &str) -> Json {
+        let mut chars = text.chars().peekable();
+        let value = Json::value(&mut chars);
+        assert_eq!(chars.next(), None, "trailing input in {text}");
+        value
+    }
+
+    fn value(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> Json {
+        match chars.peek() {
+            Some('n') => {
+                let word: String = chars.take(4).collect();
+                assert_eq!(word, "null");
+                Json::Null
+            }
+            Some('"') => Json::String(Json::string(chars)),
+            Some('[') => {
+                chars.next();
+                let mut items = Vec::new();
+                while chars.next_if_eq(&']').is_none() {
+                    chars.next_if_eq(&',');
+                    items.push(Json::value(chars));
+                }
+                Json::Array(items)
+            }
+            Some('{') => {
+                chars.next();
+                let mut fields = Vec::new();
+                while chars.next_if_eq(&'}').is_none() {
+                    chars.next_if_eq(&',');
+                    let key = Json::string(chars);
+                    assert_eq!(chars.next(), Some(':'));
+                    fields.push((key, Json::value(chars)));
+                }
+                Json::Object(fields)
+            }
+            _ => {
+                let mut number = String::new();
+                while let Some(c) = chars.next_if(|c| "-+.eE".contains(*c) || c.is_ascii_digit()) {
+                    number.push(c);
+                }
+                Json::Number(number.parse().expect("a JSON number"))
+            }
+        }
+    }
+
+    fn string(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> String {
+        assert_eq!(chars.next(), Some('"'));
+        let mut string = String::new();
+        loop {
+            match chars.next().expect("an unterminated JSON string") {
+                '"' => return string,
+                '\\' => string.push(match chars.next() {
+                    Some('n') => '\n',
+                    Some('r') => '\r',
+                    Some('t') => '\t',
+                    Some('u') => {
+                        let hex: String = chars.take(4).collect();
+                        char::from_u32(u32::from_str_radix(&hex, 16).unwrap()).unwrap()
+                    }
+                    Some(c) => c,
+                    None => panic!("an unterminated JSON escape"),
+                }),
+                c => {
+                    assert!(!c.is_control(), "raw control character in a JSON string");
+                    string.push(c);
+                }
+            }
+        }
+    }
+
+    fn get(&self, key: &str) -> &Json {
+        let Json::Object(fields) = self else {
+            panic!("{self:?} is not an object");
+        };
+        fields
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+            .unwrap_or_else(|| panic!("{self:?} has no {key}"))
+    }
+
+    fn items(&self) -> &[Json] {
+        let Json::Array(items) = self else {
+            panic!("{self:?} is not an array");
+        };
+        items
+    }
+}
+
+fn string(text: &str) -> Json {
+    Json::String(text.to_string())
+}
+
+const TOKENIZE_FIXTURE: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/tokenize/json.lox"
+);
+
+/// Checks one token object: its kind, lexeme, literal, line and span.
+fn assert_token(token: &Json, kind: &str, lexeme: &str, literal: Json, line: f64, span: [f64; 3]) {
+    assert_eq!(token.get("type"), &string("token"));
+    assert_eq!(token.get("kind"), &string(kind));
+    assert_eq!(token.get("lexeme"), &string(lexeme));
+    assert_eq!(token.get("literal"), &literal, "{lexeme}");
+    assert_eq!(token.get("line"), &Json::Number(line), "{lexeme}");
+    assert_span(token, span);
+}
+
+/// Checks one error object: its code, message, line and span.
+fn assert_error(error: &Json, code: &str, message: &str, line: f64, span: [f64; 3]) {
+    assert_eq!(error.get("type"), &string("error"));
+    assert_eq!(error.get("code"), &string(code));
+    assert_eq!(error.get("message"), &string(message));
+    assert_eq!(error.get("line"), &Json::Number(line), "{message}");
+    assert_span(error, span);
+}
+
+fn assert_span(object: &Json, [start, end, column]: [f64; 3]) {
+    let span = object.get("span");
+    assert_eq!(span.get("start"), &Json::Number(start), "{object:?}");
+    assert_eq!(span.get("end"), &Json::Number(end), "{object:?}");
+    assert_eq!(span.get("column"), &Json::Number(column), "{object:?}");
+}
+
+/// The tokens of the fixture: a string with escapes and raw control
+/// characters, a number too big for JSON, a hex number, then a stray `@`,
+/// a string with an invalid escape and a `;`.
+fn assert_fixture_tokens(tokens: &[&Json]) {
+    let [string_token, overflow, hex, semicolon, eof] = tokens else {
+        panic!("unexpected tokens: {tokens:?}");
+    };
+    assert_token(
+        string_token,
+        "STRING",
+        "\"a\\\"b\\\\\\n\u{1}\tc\"",
+        string("a\"b\\\n\u{1}\tc"),
+        1.0,
+        [0.0, 13.0, 1.0],
+    );
+    assert_token(
+        overflow,
+        "NUMBER",
+        "1e999",
+        Json::Null,
+        1.0,
+        [14.0, 19.0, 15.0],
+    );
+    assert_token(
+        hex,
+        "NUMBER",
+        "0x1F",
+        Json::Number(31.0),
+        1.0,
+        [20.0, 24.0, 21.0],
+    );
+    assert_token(
+        semicolon,
+        "SEMICOLON",
+        ";",
+        Json::Null,
+        2.0,
+        [31.0, 32.0, 7.0],
+    );
+    assert_token(eof, "EOF", "", Json::Null, 3.0, [33.0, 33.0, 1.0]);
+}
+
+fn assert_fixture_errors(errors: &[&Json]) {
+    let [stray, escape] = errors else {
+        panic!("unexpected errors: {errors:?}");
+    };
+    assert_error(
+        stray,
+        "E0001",
+        "Unexpected character: @",
+        2.0,
+        [25.0, 26.0, 1.0],
+    );
+    assert_error(
+        escape,
+        "E0005",
+        "Invalid escape sequence: \\q",
+        2.0,
+        [28.0, 30.0, 4.0],
+    );
+}
+
+#[test]
+fn tokenize_prints_json() {
+    let output = interpreter(&["tokenize", TOKENIZE_FIXTURE, "--format", "json"]);
+    assert_eq!(output.status.code(), Some(65));
+    assert!(output.stderr.is_empty());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<_> = stdout.lines().collect();
+    let [document] = lines[..] else {
+        panic!("expected a single JSON document: {stdout}");
+    };
+    let document = Json::parse(document);
+    assert_fixture_tokens(&document.get("tokens").items().iter().collect::<Vec<_>>());
+    assert_fixture_errors(&document.get("errors").items().iter().collect::<Vec<_>>());
+}
+
+#[test]
+fn tokenize_prints_ndjson_in_source_order() {
+    let output = interpreter(&["tokenize", TOKENIZE_FIXTURE, "--format", "ndjson"]);
+    assert_eq!(output.status.code(), Some(65));
+    assert!(output.stderr.is_empty());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let objects: Vec<_> = stdout.lines().map(Json::parse).collect();
+    let types: Vec<_> = objects.iter().map(|object| object.get("type")).collect();
+    assert_eq!(
+        types,
+        ["token", "token", "token", "error", "error", "token", "token"]
+            .map(string)
+            .each_ref()
+    );
+    let (errors, tokens): (Vec<_>, Vec<_>) = objects
+        .iter()
+        .partition(|object| object.get("type") == &string("error"));
+    assert_fixture_tokens(&tokens);
+    assert_fixture_errors(&errors);
+}
@@ -0,0 +1,76 @@
+//! The tree walker's garbage collector: collecting on every allocation must
+//! not change what a program does, and cycles reference counting leaks must
+//! be freed by a collection.
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn run(path: &Path, flags: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_interpreter-starter-rust"))
+        .arg("run")
+        .arg(path)
+        .args(["--backend", "tree", "--error-format", "plain"])
+        .args(flags)
+        .output()
+        .expect("failed to run the interpreter")
+}
+
+/// The value of a `[gc] <label>: <n>` line of `--gc-stats` output.
+fn stat(stderr: &str, label: &str) -> usize {
+    let prefix = format!("[gc] {label}: ");
+    stderr
+        .lines()
+        .find_map(|line| line.strip_prefix(&prefix))
+        .unwrap_or_else(|| panic!("no {label} in {stderr}"))
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn stress_collection_keeps_fixture_output() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/run");
+    let mut checked = 0;
+    for entry in fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        // Collecting on each of its 10,000 calls traces every frame still
+        // alive, which takes close to a minute in a debug build.
+        if path.extension().is_none_or(|extension| extension != "lox")
+            || path.ends_with("error_stack_overflow.lox")
+        {
+            continue;
+        }
+        let normal = run(&path, &[]);
+        let stressed = run(&path, &["--gc-stress"]);
+        let name = path.display();
+        assert_eq!(
+            String::from_utf8_lossy(&normal.stdout),
+            String::from_utf8_lossy(&stressed.stdout),
+            "stdout of {name}"
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&normal.stderr),
+            String::from_utf8_lossy(&stressed.stderr),
+            "stderr of {name}"
+        );
+        assert_eq!(
+            normal.status.code(),
+            stressed.status.code(),
+            "exit code of {name}"
+        );
+        checked += 1;
+    }
+    assert!(checked > 0, "no fixtures in {}", dir.display());
+}
+
+#[test]
+fn collection_frees_instance_closure_cycles() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/gc/cycles.lox");
+    let output = run(&path, &["--gc-stats"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "done\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stat(&stderr, "collections") > 0, "{stderr}");
+    assert_eq!(stat(&stderr, "collections skipped"), 0, "{stderr}");
+    assert!(stat(&stderr, "freed by collection") > 0, "{stderr}");
+}